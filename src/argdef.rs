@@ -1,100 +1,371 @@
 use std::str::FromStr;
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::borrow::Cow;
 use std::rc::Rc;
+use std::ffi::{OsStr, OsString};
+use std::any::Any;
+use std::path::PathBuf;
 use help::Help;
 use parse::ParseError;
+use style::Sink;
+
+/// A value that a target has read but not stored yet. Only the target that
+/// prepared it knows what it holds.
+pub type Prepared = Box<Any>;
+
+/// The message of the panic when a target is given a value that it didn't 
+/// prepare, which the parser never does.
+const FOREIGN: &'static str = "A target was given a value prepared by another target";
+
+/// The message of the panic when `FromStr` rejects a value that it accepted
+/// while it was prepared.
+const REJECTED: &'static str = "A value was rejected after it had been accepted";
 
 /// Allows every type that is FromStr to be read from an argument.
 pub trait SingleTarget: Debug {
-    /// Parses the value and updates self with it.
-    fn parse(&mut self, value: &str) -> Result<(), String>;
+    /// Parses the value, without updating self.
+    fn prepare(&self, value: &str) -> Result<Prepared, String>;
+    
+    /// Parses a value that isn't valid UTF-8, as it was given to `parse_os`.
+    /// By default the value is converted lossily and parsed by `prepare`.
+    fn prepare_raw(&self, value: &OsStr) -> Result<Prepared, String> {
+        self.prepare(&value.to_string_lossy())
+    }
+    
+    /// Updates self with a value returned by `prepare`. This can't fail, so
+    /// that no target is updated unless every argument was accepted.
+    fn commit(&mut self, prepared: Prepared);
 }
 
-impl<T> SingleTarget for T where T: Debug + FromStr {
-    // TODO: Better info here.
-    fn parse(&mut self, value: &str) -> Result<(), String> {
-        let value = match <T as FromStr>::from_str(value) {
-            Ok(val) => val,
-            Err(_) => return Err(format!("Could not parse and convert '{}'", value)),
-        };
-        *self = value;
-        Ok(())
+// The `FromStr` targets prepare the text of the value once it has been
+// checked, and convert it again when it's committed, so that `T` doesn't need
+// to be `'static` to be prepared.
+impl<T> SingleTarget for T where T: Debug + FromStr, T::Err: Display {
+    fn prepare(&self, value: &str) -> Result<Prepared, String> {
+        convert::<T>(value)?;
+        Ok(Box::new(value.to_string()))
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        let value: Box<String> = prepared.downcast().expect(FOREIGN);
+        *self = convert(&value).expect(REJECTED);
     }
 }
 
 /// Allows every type that is FromStr to be read from an argument.
 pub trait OptionTarget: Debug {
-    /// Parses the value and updates self with it.
-    fn parse(&mut self, value: &str) -> Result<(), String>;
+    /// Parses the value, without updating self.
+    fn prepare(&self, value: &str) -> Result<Prepared, String>;
+    
+    /// Parses a value that isn't valid UTF-8, as it was given to `parse_os`.
+    /// By default the value is converted lossily and parsed by `prepare`.
+    fn prepare_raw(&self, value: &OsStr) -> Result<Prepared, String> {
+        self.prepare(&value.to_string_lossy())
+    }
+    
+    /// Updates self with a value returned by `prepare`. This can't fail.
+    fn commit(&mut self, prepared: Prepared);
 }
 
-impl<T> OptionTarget for Option<T> where T: Debug + FromStr {
-    // TODO: Better info here.
-    fn parse(&mut self, value: &str) -> Result<(), String> {
-        let value = match <T as FromStr>::from_str(value) {
-            Ok(val) => val,
-            Err(_) => return Err(format!("Could not parse and convert '{}'", value)),
-        };
-        *self = Some(value);
-        Ok(())
+impl<T> OptionTarget for Option<T> where T: Debug + FromStr, T::Err: Display {
+    fn prepare(&self, value: &str) -> Result<Prepared, String> {
+        convert::<T>(value)?;
+        Ok(Box::new(value.to_string()))
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        let value: Box<String> = prepared.downcast().expect(FOREIGN);
+        *self = Some(convert(&value).expect(REJECTED));
     }
 }
 
 /// Allows a collection to be extended with values read from arguments.
 pub trait CollectionTarget: Debug {
-    /// Parses the value and adds it to this collection.
-    fn parse_and_add(&mut self, value: &str) -> Result<(), String>;
+    /// Parses all the values that are to be added, without updating self, 
+    /// so that the collection can check them together, for example for 
+    /// duplicates. Fails with the index of the rejected value.
+    fn prepare(&self, values: &[&str]) -> Result<Prepared, (usize, String)>;
+    
+    /// Parses values of which some aren't valid UTF-8, as they were given to
+    /// `parse_os`. By default the values are converted lossily and parsed by
+    /// `prepare`.
+    fn prepare_raw(&self, values: &[&OsStr]) -> Result<Prepared, (usize, String)> {
+        let converted: Vec<_> = values.iter().map(|value| value.to_string_lossy()).collect();
+        let texts: Vec<&str> = converted.iter().map(|text| text.as_ref()).collect();
+        self.prepare(&texts)
+    }
+    
+    /// Adds the values returned by `prepare`. This can't fail.
+    fn commit(&mut self, prepared: Prepared);
 }
 
-impl<T> CollectionTarget for Vec<T> where T: Debug + FromStr {
-    fn parse_and_add(&mut self, value: &str) -> Result<(), String> {
-        let value = match <T as FromStr>::from_str(value) {
-            Ok(val) => val,
-            Err(_) => return Err(format!("Could not parse and convert '{}'", value)),
-        };
+impl<T> CollectionTarget for Vec<T> where T: Debug + FromStr, T::Err: Display {
+    fn prepare(&self, values: &[&str]) -> Result<Prepared, (usize, String)> {
+        for (i, value) in values.iter().enumerate() {
+            convert::<T>(value).map_err(|cause| (i, cause))?;
+        }
+        let texts: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+        Ok(Box::new(texts))
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        let texts: Box<Vec<String>> = prepared.downcast().expect(FOREIGN);
+        self.extend(texts.iter().map(|value| convert::<T>(value).expect(REJECTED)));
+    }
+}
+
+/// Allows a value to be read from an argument as it was given, even if it
+/// isn't valid UTF-8. See `parse_os`.
+pub trait OsTarget: Debug {
+    /// Reads the value, without updating self.
+    fn prepare_os(&self, value: &OsStr) -> Result<Prepared, String>;
+    
+    /// Updates self with a value returned by `prepare_os`. Collections add 
+    /// the value. This can't fail.
+    fn commit_os(&mut self, prepared: Prepared);
+}
+
+impl OsTarget for OsString {
+    fn prepare_os(&self, value: &OsStr) -> Result<Prepared, String> {
+        Ok(Box::new(value.to_os_string()))
+    }
+    
+    fn commit_os(&mut self, prepared: Prepared) {
+        *self = *prepared.downcast().expect(FOREIGN);
+    }
+}
+
+impl OsTarget for PathBuf {
+    fn prepare_os(&self, value: &OsStr) -> Result<Prepared, String> {
+        Ok(Box::new(PathBuf::from(value)))
+    }
+    
+    fn commit_os(&mut self, prepared: Prepared) {
+        *self = *prepared.downcast().expect(FOREIGN);
+    }
+}
+
+impl<T> OsTarget for Option<T> where T: OsTarget + Default {
+    fn prepare_os(&self, value: &OsStr) -> Result<Prepared, String> {
+        T::default().prepare_os(value)
+    }
+    
+    fn commit_os(&mut self, prepared: Prepared) {
+        let mut value = T::default();
+        value.commit_os(prepared);
+        *self = Some(value);
+    }
+}
+
+impl<T> OsTarget for Vec<T> where T: OsTarget + Default {
+    fn prepare_os(&self, value: &OsStr) -> Result<Prepared, String> {
+        T::default().prepare_os(value)
+    }
+    
+    fn commit_os(&mut self, prepared: Prepared) {
+        let mut value = T::default();
+        value.commit_os(prepared);
         self.push(value);
-        Ok(())
     }
 }
 
+/// Lets an `OsTarget` be used where the other kinds of targets are, by 
+/// giving it the values as they were passed to `parse_os`.
+#[derive(Debug)]
+struct OsAdapter<'tar>(&'tar mut OsTarget);
+
+impl<'tar> SingleTarget for OsAdapter<'tar> {
+    fn prepare(&self, value: &str) -> Result<Prepared, String> {
+        self.0.prepare_os(OsStr::new(value))
+    }
+    
+    fn prepare_raw(&self, value: &OsStr) -> Result<Prepared, String> {
+        self.0.prepare_os(value)
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        self.0.commit_os(prepared)
+    }
+}
+
+impl<'tar> OptionTarget for OsAdapter<'tar> {
+    fn prepare(&self, value: &str) -> Result<Prepared, String> {
+        self.0.prepare_os(OsStr::new(value))
+    }
+    
+    fn prepare_raw(&self, value: &OsStr) -> Result<Prepared, String> {
+        self.0.prepare_os(value)
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        self.0.commit_os(prepared)
+    }
+}
+
+impl<'tar> CollectionTarget for OsAdapter<'tar> {
+    fn prepare(&self, values: &[&str]) -> Result<Prepared, (usize, String)> {
+        let values: Vec<&OsStr> = values.iter().map(OsStr::new).collect();
+        CollectionTarget::prepare_raw(self, &values)
+    }
+    
+    fn prepare_raw(&self, values: &[&OsStr]) -> Result<Prepared, (usize, String)> {
+        let prepared = values.iter().enumerate()
+            .map(|(i, value)| self.0.prepare_os(value).map_err(|cause| (i, cause)))
+            .collect::<Result<Vec<Prepared>, _>>()?;
+        Ok(Box::new(prepared))
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        let prepared: Box<Vec<Prepared>> = prepared.downcast().expect(FOREIGN);
+        for value in prepared.into_iter() {
+            self.0.commit_os(value);
+        }
+    }
+}
+
+impl<'a> SingleTarget for &'a mut SingleTarget {
+    fn prepare(&self, value: &str) -> Result<Prepared, String> {
+        (**self).prepare(value)
+    }
+    
+    fn prepare_raw(&self, value: &OsStr) -> Result<Prepared, String> {
+        (**self).prepare_raw(value)
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        (**self).commit(prepared)
+    }
+}
+
+impl<'a> OptionTarget for &'a mut OptionTarget {
+    fn prepare(&self, value: &str) -> Result<Prepared, String> {
+        (**self).prepare(value)
+    }
+    
+    fn prepare_raw(&self, value: &OsStr) -> Result<Prepared, String> {
+        (**self).prepare_raw(value)
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        (**self).commit(prepared)
+    }
+}
+
+impl<'a> CollectionTarget for &'a mut CollectionTarget {
+    fn prepare(&self, values: &[&str]) -> Result<Prepared, (usize, String)> {
+        (**self).prepare(values)
+    }
+    
+    fn prepare_raw(&self, values: &[&OsStr]) -> Result<Prepared, (usize, String)> {
+        (**self).prepare_raw(values)
+    }
+    
+    fn commit(&mut self, prepared: Prepared) {
+        (**self).commit(prepared)
+    }
+}
+
+/// Parses the value, describing the error of `FromStr` if it fails.
+fn convert<T: FromStr>(value: &str) -> Result<T, String> where T::Err: Display {
+    <T as FromStr>::from_str(value).map_err(|err| err.to_string())
+}
+
 pub type SubCmd<'def> = Box<FnMut(String, &[&str]) -> Result<(), ParseError<'def>>>;
 
+/// The callback of an interrupt, which gets the value of the interrupt if it 
+/// takes one, and the sink to write its output to.
+pub type InterruptFn<'def> = Box<FnMut(Rc<Help<'def>>, Option<&str>, &mut Sink)>;
+
 /// The description of an expected argument.
 //#[derive(Debug)]
 pub struct ArgDef<'def, 'tar> {
     pub name: Cow<'def, str>,
     pub kind: ArgDefKind<'def, 'tar>,
     pub help_desc: Option<Cow<'def, str>>,
+    /// An environment variable read when the argument is not given.
+    pub env: Option<Cow<'def, str>>,
+    /// The value used when the argument is given neither as an argument,
+    /// nor through the environment.
+    pub default: Option<Cow<'def, str>>,
+    /// Arguments that must be given when this one is.
+    pub requires: Vec<Cow<'def, str>>,
+    /// Arguments that cannot be given when this one is.
+    pub conflicts: Vec<Cow<'def, str>>,
+    /// The only values accepted by this argument, unless empty.
+    pub choices: Vec<Cow<'def, str>>,
+    /// Whether the choices are matched regardless of case.
+    pub ignore_case: bool,
+    /// Whether this option is accepted by the subcommands as well.
+    pub global: bool,
+    /// Other names of this subcommand.
+    pub aliases: Vec<Cow<'def, str>>,
+    /// Whether this argument is left out of the help.
+    pub hidden: bool,
 }
 
+/// The kind of an argument, along with its target. Arguments without a
+/// target are unbound, and are only read into `Matches`.
 //#[derive(Debug)]
 pub enum ArgDefKind<'def, 'tar> {
     Positional { 
-        target: &'tar mut SingleTarget,
+        target: Option<Box<SingleTarget + 'tar>>,
     },
     Subcommand {
         handler: SubCmd<'def>,
+        /// The arguments that the handler reads, which are only used to 
+        /// describe the subcommand. See `ArgDef::described_by`.
+        described: Vec<ArgDef<'def, 'tar>>,
+    },
+    /// A subcommand given by its own definitions, which are read from the
+    /// arguments after its name.
+    Command {
+        children: Vec<ArgDef<'def, 'tar>>,
     },
     Trail { 
         optional: bool, 
-        target: &'tar mut CollectionTarget,
+        target: Option<Box<CollectionTarget + 'tar>>,
+    },
+    Passthrough {
+        target: Option<&'tar mut Vec<String>>,
+    },
+    Group {
+        members: Vec<Cow<'def, str>>,
+        exclusive: bool,
+        required: bool,
     },
     Flag {
         short: Option<Cow<'def, str>>,
-        target: &'tar mut bool,
+        target: Option<&'tar mut bool>,
+        /// Whether `--no-<name>` sets the target to false.
+        negatable: bool,
+    },
+    /// A flag that is turned on with `--<name>` and off with `--no-<name>`,
+    /// and whose target is left as it is when neither is given.
+    Tristate {
+        short: Option<Cow<'def, str>>,
+        target: Option<&'tar mut Option<bool>>,
     },
     Count {
         short: Option<Cow<'def, str>>,
-        target: &'tar mut usize,
+        target: Option<&'tar mut usize>,
     },
     OptArg {
         short: Option<Cow<'def, str>>,
-        target: &'tar mut OptionTarget,
+        target: Option<Box<OptionTarget + 'tar>>,
+        required: bool,
+    },
+    Repeated {
+        short: Option<Cow<'def, str>>,
+        target: Option<Box<CollectionTarget + 'tar>>,
+        /// The character the values are split at, if any.
+        delimiter: Option<char>,
+        required: bool,
     },
     Interrupt {
         short: Option<Cow<'def, str>>,
-        callback: Box<FnMut(Rc<Help<'def>>)>,
+        callback: InterruptFn<'def>,
+        /// The name of the value read after the interrupt, if it takes one.
+        value_name: Option<Cow<'def, str>>,
     },
 }
 
@@ -107,6 +378,15 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
             name: name.into(),
             kind: kind,
             help_desc: None,
+            env: None,
+            default: None,
+            requires: Vec::new(),
+            conflicts: Vec::new(),
+            choices: Vec::new(),
+            ignore_case: false,
+            global: false,
+            aliases: Vec::new(),
+            hidden: false,
         }
     }
     
@@ -114,10 +394,12 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     ///
     /// The target value will be updated after the parse, as long as the parse 
     /// succeeds and is not interrupted by an `interrupt`-type argument.
+    /// This holds for every kind of target: nothing is written until all
+    /// arguments have been read and validated.
     pub fn pos<N>(name: N, target: &'tar mut SingleTarget) -> ArgDef<'def, 'tar> 
       where N: Into<Cow<'def, str>> 
     {
-        ArgDef::new(name, ArgDefKind::Positional { target })
+        ArgDef::new(name, ArgDefKind::Positional { target: Some(Box::new(target)) })
     }
     
     /// Creates a description of a `trail`-type argument.
@@ -127,7 +409,38 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     pub fn trail<N>(name: N, optional: bool, target: &'tar mut CollectionTarget) -> ArgDef<'def, 'tar>
       where N: Into<Cow<'def, str>>
     {
-        ArgDef::new(name, ArgDefKind::Trail { optional, target })
+        ArgDef::new(name, ArgDefKind::Trail { optional, target: Some(Box::new(target)) })
+    }
+    
+    /// Creates a description of a `passthrough`-type argument.
+    ///
+    /// Every argument after `--` is added to the target as it was given, 
+    /// without being read as an option. This is for arguments meant for 
+    /// another program, like in `cargo run -- --help`.
+    ///
+    /// Positionals without a default that weren't given before `--` take the
+    /// first arguments after it, the passthrough gets the rest. The trail and
+    /// the positionals with a default are never given arguments after `--`.
+    pub fn passthrough<N>(name: N, target: &'tar mut Vec<String>) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Passthrough { target: Some(target) })
+    }
+    
+    /// Creates a description of a group of other arguments, given by name.
+    ///
+    /// The group doesn't read anything by itself, but can be made `exclusive`
+    /// so that at most one of its members may be given, and `required` so 
+    /// that at least one of them must be given. Its name can be used with
+    /// `requires` and `conflicts_with` to refer to any of its members.
+    pub fn group<N, I, M>(name: N, members: I) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>, I: IntoIterator<Item=M>, M: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Group {
+            members: members.into_iter().map(|m| m.into()).collect(),
+            exclusive: false,
+            required: false,
+        })
     }
     
     /// Creates a description of a subcommand.
@@ -135,7 +448,35 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
       where N: Into<Cow<'def, str>>,
             F: 'static + FnMut(String, &[&str]) -> Result<(), ParseError<'def>>
     {
-        ArgDef::new(name, ArgDefKind::Subcommand { handler: Box::new(handler) })
+        ArgDef::new(name, ArgDefKind::Subcommand { handler: Box::new(handler), described: Vec::new() })
+    }
+    
+    /// Creates a description of a subcommand with its own arguments, which
+    /// may be subcommands themselves.
+    ///
+    /// The arguments after the name of the subcommand are read with the
+    /// children, along with the `global` options of the enclosing commands.
+    /// Nothing is written to the targets of any level unless every level
+    /// parses successfully.
+    ///
+    /// # Example
+    /// ```
+    /// # use playground::*;
+    /// let (mut verbose, mut name) = (false, String::new());
+    /// parse_plain("git", &["remote", "add", "-v", "origin"], vec![
+    ///     ArgDef::flag("verbose", &mut verbose).short("v").global(),
+    ///     ArgDef::subcommand("remote", vec![
+    ///         ArgDef::subcommand("add", vec![
+    ///             ArgDef::pos("name", &mut name),
+    ///         ]),
+    ///     ]),
+    /// ]).unwrap();
+    /// assert_eq!((true, "origin"), (verbose, name.as_str()));
+    /// ```
+    pub fn subcommand<N>(name: N, children: Vec<ArgDef<'def, 'tar>>) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Command { children })
     }
     
     /// Creates a description of an `interrupt`-type argument.
@@ -143,11 +484,32 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     /// When the identifier for this argument is passed, the callback is run,
     /// and the parsing is interrupted. This is for options that should interrupt
     /// the parse when encountered, such as `--help` and `--version`.
-    pub fn interrupt<N, F>(name: N, callback: F) -> ArgDef<'def, 'tar>
-      where N: Into<Cow<'def, str>>, F: FnMut(Rc<Help<'def>>) + 'static
+    ///
+    /// The callback writes its output to the given sink, which is the
+    /// `output_sink` of the parse settings, stdout by default.
+    pub fn interrupt<N, F>(name: N, mut callback: F) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>, F: FnMut(Rc<Help<'def>>, &mut Sink) + 'static
     {
         ArgDef::new(name, ArgDefKind::Interrupt { 
-            short: None, callback: Box::new(callback)
+            short: None, 
+            callback: Box::new(move |help, _, sink| callback(help, sink)),
+            value_name: None,
+        })
+    }
+    
+    /// Creates a description of an `interrupt`-type argument that takes a
+    /// value, like `--completions bash`.
+    ///
+    /// The value is passed to the callback as it was given.
+    pub fn interrupt_arg<N, V, F>(name: N, value_name: V, mut callback: F) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>, V: Into<Cow<'def, str>>, 
+            F: FnMut(Rc<Help<'def>>, &str, &mut Sink) + 'static
+    {
+        ArgDef::new(name, ArgDefKind::Interrupt { 
+            short: None, 
+            // INVARIANT: The parse always reads a value for these
+            callback: Box::new(move |help, value, sink| callback(help, value.unwrap(), sink)),
+            value_name: Some(value_name.into()),
         })
     }
     
@@ -157,7 +519,65 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     pub fn option<N>(name: N, target: &'tar mut OptionTarget) -> ArgDef<'def, 'tar>
       where N: Into<Cow<'def, str>>
     {
-        ArgDef::new(name, ArgDefKind::OptArg { short: None, target })
+        ArgDef::new(name, ArgDefKind::OptArg { short: None, target: Some(Box::new(target)), required: false })
+    }
+    
+    /// Creates a description of an option that can be given several times,
+    /// like `--include a --include b`.
+    /// 
+    /// Every value is parsed and added to the target.
+    pub fn repeated<N>(name: N, target: &'tar mut CollectionTarget) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Repeated { 
+            short: None, 
+            target: Some(Box::new(target)), 
+            delimiter: None, 
+            required: false,
+        })
+    }
+    
+    /// Creates a description of a positional argument whose value is given 
+    /// to the target as it was passed, even if it isn't valid UTF-8, like a
+    /// `PathBuf`. See `parse_os`.
+    pub fn os_pos<N>(name: N, target: &'tar mut OsTarget) -> ArgDef<'def, 'tar> 
+      where N: Into<Cow<'def, str>> 
+    {
+        ArgDef::new(name, ArgDefKind::Positional { target: Some(Box::new(OsAdapter(target))) })
+    }
+    
+    /// Creates a description of a trail whose values are added to the target
+    /// as they were passed, like to a `Vec<PathBuf>`. See `parse_os`.
+    pub fn os_trail<N>(name: N, optional: bool, target: &'tar mut OsTarget) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Trail { optional, target: Some(Box::new(OsAdapter(target))) })
+    }
+    
+    /// Creates a description of an option whose value is given to the target
+    /// as it was passed, like to an `Option<PathBuf>`. See `parse_os`.
+    pub fn os_option<N>(name: N, target: &'tar mut OsTarget) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::OptArg { 
+            short: None, 
+            target: Some(Box::new(OsAdapter(target))), 
+            required: false,
+        })
+    }
+    
+    /// Creates a description of a repeated option whose values are added to
+    /// the target as they were passed, like to a `Vec<PathBuf>`. See 
+    /// `parse_os`.
+    pub fn os_repeated<N>(name: N, target: &'tar mut OsTarget) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Repeated { 
+            short: None, 
+            target: Some(Box::new(OsAdapter(target))), 
+            delimiter: None, 
+            required: false,
+        })
     }
     
     /// Creates a description of a `flag`-type argument.
@@ -166,7 +586,19 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     pub fn flag<N>(name: N, target: &'tar mut bool) -> ArgDef<'def, 'tar>
       where N: Into<Cow<'def, str>>
     {
-        ArgDef::new(name, ArgDefKind::Flag { short: None, target })
+        ArgDef::new(name, ArgDefKind::Flag { short: None, target: Some(target), negatable: false })
+    }
+    
+    /// Creates a description of a flag that can be turned on and off, like
+    /// `--cache` and `--no-cache`.
+    /// 
+    /// The target is set to `Some(true)` or `Some(false)` by the last of
+    /// them given, and left as it is when neither is given, so that "not 
+    /// given" can be told apart from "turned off".
+    pub fn tristate<N>(name: N, target: &'tar mut Option<bool>) -> ArgDef<'def, 'tar>
+      where N: Into<Cow<'def, str>>
+    {
+        ArgDef::new(name, ArgDefKind::Tristate { short: None, target: Some(target) })
     }
     
     /// Creates a description of a `count`-type argument.
@@ -175,18 +607,74 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     pub fn count<N>(name: N, target: &'tar mut usize) -> ArgDef<'def, 'tar>
       where N: Into<Cow<'def, str>> 
     {
-        ArgDef::new(name, ArgDefKind::Count { short: None, target })
+        ArgDef::new(name, ArgDefKind::Count { short: None, target: Some(target) })
+    }
+    
+    /// Creates a description of a positional argument without a target, to
+    /// be read with `parse_matches`.
+    pub fn unbound_pos<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::Positional { target: None })
+    }
+    
+    /// Creates a description of a trail without a target, to be read with 
+    /// `parse_matches`.
+    pub fn unbound_trail<N>(name: N, optional: bool) -> ArgDef<'def, 'tar> 
+      where N: Into<Cow<'def, str>> 
+    {
+        ArgDef::new(name, ArgDefKind::Trail { optional, target: None })
+    }
+    
+    /// Creates a description of a passthrough argument without a target, to
+    /// be read with `parse_matches`.
+    pub fn unbound_passthrough<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::Passthrough { target: None })
+    }
+    
+    /// Creates a description of an option without a target, to be read with 
+    /// `parse_matches`.
+    pub fn unbound_option<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::OptArg { short: None, target: None, required: false })
+    }
+    
+    /// Creates a description of a repeated option without a target, to be 
+    /// read with `parse_matches`.
+    pub fn unbound_repeated<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::Repeated { 
+            short: None, 
+            target: None, 
+            delimiter: None, 
+            required: false,
+        })
+    }
+    
+    /// Creates a description of a flag without a target, to be read with 
+    /// `parse_matches`.
+    pub fn unbound_flag<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::Flag { short: None, target: None, negatable: false })
+    }
+    
+    /// Creates a description of a tristate flag without a target, to be read
+    /// with `parse_matches`. Its value is `true` or `false` when given.
+    pub fn unbound_tristate<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::Tristate { short: None, target: None })
+    }
+    
+    /// Creates a description of a count without a target, to be read with 
+    /// `parse_matches`.
+    pub fn unbound_count<N>(name: N) -> ArgDef<'def, 'tar> where N: Into<Cow<'def, str>> {
+        ArgDef::new(name, ArgDefKind::Count { short: None, target: None })
     }
     
     /// Adds a short identifier for this option, like `-h` for `help`.
     ///
-    /// **NOTE**: This method PANICS if used on a `positional`, `trail` or 
-    /// `subcommand` description.
+    /// **NOTE**: This method PANICS if used on a `positional`, `trail`, 
+    /// `passthrough`, `subcommand` or `group` description.
     ///
     /// # Example
     /// ```
+    /// # use playground::*;
     /// let mut eat_ice_cream = false;
-    /// parse(&["-e"], &[
+    /// parse_plain("prog", &["-e"], vec![
     ///     ArgDef::flag("eat_ice_cream", &mut eat_ice_cream).short("e"),
     /// ]).unwrap();
     /// assert_eq!(true, eat_ice_cream);
@@ -194,17 +682,278 @@ impl<'def, 'tar> ArgDef<'def, 'tar> {
     pub fn short<N>(mut self, short: N) -> Self where N: Into<Cow<'def, str>> {
         use self::ArgDefKind::*;
         self.kind = match self.kind {
-            Positional { .. } | Trail { .. } | Subcommand { .. } => {
-                panic!("Positional, trail and subcommand arguments cannot have a short identifier");
+            Positional { .. } | Trail { .. } | Passthrough { .. } | Subcommand { .. } | 
+            Command { .. } | Group { .. } => {
+                panic!("Positional, trail, passthrough, subcommand and group arguments cannot have a short identifier");
             },
-            Flag { target, .. } => Flag { short: Some(short.into()), target },
+            Flag { target, negatable, .. } => Flag { short: Some(short.into()), target, negatable },
+            Tristate { target, .. } => Tristate { short: Some(short.into()), target },
             Count { target, .. } => Count { short: Some(short.into()), target },
-            OptArg { target, .. } => OptArg { short: Some(short.into()), target },
-            Interrupt { callback, .. } => Interrupt { short: Some(short.into()), callback },
+            OptArg { target, required, .. } => OptArg { short: Some(short.into()), target, required },
+            Repeated { target, delimiter, required, .. } => Repeated { 
+                short: Some(short.into()), target, delimiter, required,
+            },
+            Interrupt { callback, value_name, .. } => Interrupt { 
+                short: Some(short.into()), callback, value_name,
+            },
         };
         self
     }
     
+    /// Binds this argument to an environment variable, which is read when
+    /// the argument is not given on the command line.
+    ///
+    /// The value is parsed like one given on the command line. Flags accept
+    /// `1`, `true`, `yes` and `on` (or `0`, `false`, `no`, `off` and nothing),
+    /// and counts accept a number. A trail gets the value as its only element,
+    /// and so does a repeated option unless it has a delimiter.
+    ///
+    /// **NOTE**: This method PANICS if used on a `subcommand`, `passthrough`,
+    /// `interrupt` or `group` description.
+    pub fn env<N>(mut self, var: N) -> Self where N: Into<Cow<'def, str>> {
+        match self.kind {
+            ArgDefKind::Subcommand { .. } | ArgDefKind::Command { .. } | 
+            ArgDefKind::Passthrough { .. } | ArgDefKind::Interrupt { .. } | 
+            ArgDefKind::Group { .. } => {
+                panic!("Subcommand, passthrough, interrupt and group arguments cannot be bound to an environment variable");
+            }
+            _ => {}
+        }
+        self.env = Some(var.into());
+        self
+    }
+    
+    /// Describes the arguments that the handler of this `cmd` subcommand 
+    /// reads, so that they are listed in the help, the `help` subcommand, the
+    /// completions and the documentation. The definitions are never parsed, 
+    /// so they are usually unbound.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but a `cmd`.
+    pub fn described_by(mut self, definitions: Vec<ArgDef<'def, 'tar>>) -> Self {
+        match self.kind {
+            ArgDefKind::Subcommand { ref mut described, .. } => *described = definitions,
+            _ => panic!("Only cmd subcommands can be described by definitions"),
+        }
+        self
+    }
+    
+    /// Sets a default value for this argument, which is parsed by the target
+    /// when the argument isn't given. This makes positional arguments optional.
+    /// A trail gets the value as its only element, and so does a repeated 
+    /// option unless it has a delimiter.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but an `option`,
+    /// `repeated`, `positional` or `trail` description.
+    pub fn default<N>(mut self, value: N) -> Self where N: Into<Cow<'def, str>> {
+        match self.kind {
+            ArgDefKind::OptArg { .. } | ArgDefKind::Repeated { .. } | 
+            ArgDefKind::Positional { .. } | ArgDefKind::Trail { .. } => {}
+            _ => panic!("Only option, repeated, positional and trail arguments can have a default value"),
+        }
+        self.default = Some(value.into());
+        self
+    }
+    
+    /// Limits the values of this argument to the given choices. Other values
+    /// are rejected before they are parsed by the target, or before the 
+    /// callback of an `interrupt_arg` is run.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but an `option`,
+    /// `repeated`, `positional`, `trail` or `interrupt_arg` description.
+    ///
+    /// # Example
+    /// ```
+    /// # use playground::*;
+    /// let mut format: Option<String> = None;
+    /// parse_plain("prog", &["--format", "yaml"], vec![
+    ///     ArgDef::option("format", &mut format).choices(&["json", "yaml", "text"]),
+    /// ]).unwrap();
+    /// assert_eq!(Some("yaml".to_string()), format);
+    /// ```
+    pub fn choices<I, C>(mut self, choices: I) -> Self where I: IntoIterator<Item=C>, C: AsRef<str> {
+        match self.kind {
+            ArgDefKind::OptArg { .. } | ArgDefKind::Repeated { .. } | 
+            ArgDefKind::Positional { .. } | ArgDefKind::Trail { .. } |
+            ArgDefKind::Interrupt { value_name: Some(_), .. } => {}
+            _ => panic!("Only option, repeated, positional, trail and interrupt_arg arguments can have choices"),
+        }
+        self.choices = choices.into_iter()
+            .map(|choice| Cow::Owned(choice.as_ref().to_string()))
+            .collect();
+        self
+    }
+    
+    /// Matches the choices of this argument regardless of case. The target
+    /// gets the choice as it was defined, so `--format JSON` gives `json`.
+    pub fn ignore_case(mut self) -> Self {
+        self.ignore_case = true;
+        self
+    }
+    
+    /// Allows at most one member of this group to be given.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but a `group`.
+    pub fn exclusive(mut self) -> Self {
+        match self.kind {
+            ArgDefKind::Group { ref mut exclusive, .. } => *exclusive = true,
+            _ => panic!("Only groups can be exclusive"),
+        }
+        self
+    }
+    
+    /// Requires this option to be given, or at least one member of this 
+    /// group. Values from the environment, config files and defaults count as
+    /// given.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but an `option`,
+    /// `repeated` or `group` description.
+    pub fn required(mut self) -> Self {
+        match self.kind {
+            ArgDefKind::Group { ref mut required, .. } |
+            ArgDefKind::OptArg { ref mut required, .. } |
+            ArgDefKind::Repeated { ref mut required, .. } => *required = true,
+            _ => panic!("Only option, repeated and group arguments can be required"),
+        }
+        self
+    }
+    
+    /// Splits every value of this repeated option at the given character, so
+    /// that `--include a,b` adds both `a` and `b`.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but a `repeated` 
+    /// description.
+    pub fn delimiter(mut self, delim: char) -> Self {
+        match self.kind {
+            ArgDefKind::Repeated { ref mut delimiter, .. } => *delimiter = Some(delim),
+            _ => panic!("Only repeated arguments can have a delimiter"),
+        }
+        self
+    }
+    
+    /// Lets this flag be turned off with `--no-<name>`, like `--no-cache`
+    /// for `cache`. The last of `--cache` and `--no-cache` given wins, and
+    /// the target is set to false when `--no-cache` does.
+    ///
+    /// Tristate flags are always negatable.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but a `flag` or
+    /// `tristate` description.
+    pub fn negatable(mut self) -> Self {
+        match self.kind {
+            ArgDefKind::Flag { ref mut negatable, .. } => *negatable = true,
+            ArgDefKind::Tristate { .. } => {}
+            _ => panic!("Only flags can be negatable"),
+        }
+        self
+    }
+    
+    /// Requires the named argument or group to be given whenever this 
+    /// argument is. Values from the environment and config files count as
+    /// given, but default values don't.
+    ///
+    /// **NOTE**: This method PANICS if used on a `subcommand`, `interrupt`
+    /// or `group` description.
+    pub fn requires<N>(mut self, name: N) -> Self where N: Into<Cow<'def, str>> {
+        self.assert_relatable();
+        self.requires.push(name.into());
+        self
+    }
+    
+    /// Forbids the named argument or group from being given whenever this 
+    /// argument is. Values from the environment and config files count as
+    /// given, but default values don't.
+    ///
+    /// **NOTE**: This method PANICS if used on a `subcommand`, `interrupt`
+    /// or `group` description.
+    pub fn conflicts_with<N>(mut self, name: N) -> Self where N: Into<Cow<'def, str>> {
+        self.assert_relatable();
+        self.conflicts.push(name.into());
+        self
+    }
+    
+    /// Makes this option accepted by the subcommands defined with 
+    /// `subcommand` as well, at any depth. Wherever it is given, it is
+    /// written to the same target, and read into the `Matches` of the 
+    /// deepest subcommand that was given.
+    ///
+    /// A global `help` interrupt gets the help of the subcommand it is given
+    /// to, with its full command path.
+    ///
+    /// **NOTE**: This method PANICS if used on a `positional`, `trail`,
+    /// `passthrough`, `subcommand` or `group` description.
+    pub fn global(mut self) -> Self {
+        match self.kind {
+            ArgDefKind::Flag { .. } | ArgDefKind::Tristate { .. } | ArgDefKind::Count { .. } | 
+            ArgDefKind::OptArg { .. } | ArgDefKind::Repeated { .. } | ArgDefKind::Interrupt { .. } => {}
+            _ => panic!("Only option, repeated, flag, count and interrupt arguments can be global"),
+        }
+        self.global = true;
+        self
+    }
+    
+    /// Adds another name for this subcommand, like `rm` for `remove`. The
+    /// handler gets the command path with the actual name.
+    ///
+    /// **NOTE**: This method PANICS if used on anything but a `subcommand`.
+    pub fn alias<N>(mut self, alias: N) -> Self where N: Into<Cow<'def, str>> {
+        match self.kind {
+            ArgDefKind::Subcommand { .. } | ArgDefKind::Command { .. } => {}
+            _ => panic!("Only subcommands can have aliases"),
+        }
+        self.aliases.push(alias.into());
+        self
+    }
+    
+    /// Leaves this option or subcommand out of the help, the generated 
+    /// documentation and completions, and the suggestions for unknown names. 
+    /// It is read like any other.
+    ///
+    /// **NOTE**: This method PANICS if used on a `positional`, `trail`,
+    /// `passthrough` or `group` description.
+    pub fn hidden(mut self) -> Self {
+        match self.kind {
+            ArgDefKind::Positional { .. } | ArgDefKind::Trail { .. } | 
+            ArgDefKind::Passthrough { .. } | ArgDefKind::Group { .. } => {
+                panic!("Positional, trail, passthrough and group arguments cannot be hidden");
+            }
+            _ => {}
+        }
+        self.hidden = true;
+        self
+    }
+    
+    fn assert_relatable(&self) {
+        match self.kind {
+            ArgDefKind::Subcommand { .. } | ArgDefKind::Command { .. } | 
+            ArgDefKind::Interrupt { .. } | ArgDefKind::Group { .. } => {
+                panic!("Subcommand, interrupt and group arguments cannot require or conflict with others");
+            }
+            _ => {}
+        }
+    }
+    
+    /// Returns the environment variable bound to this argument, if any.
+    ///
+    /// Options, repeated options, flags and counts without an explicit 
+    /// variable are mapped from their name when a prefix is given, so that 
+    /// `log-level` with the prefix `APP` becomes `APP_LOG_LEVEL`.
+    pub fn env_var(&self, prefix: Option<&str>) -> Option<Cow<'def, str>> {
+        if let Some(ref var) = self.env {
+            return Some(var.clone());
+        }
+        match (&self.kind, prefix) {
+            (&ArgDefKind::Flag { .. }, Some(prefix)) |
+            (&ArgDefKind::Tristate { .. }, Some(prefix)) |
+            (&ArgDefKind::Count { .. }, Some(prefix)) |
+            (&ArgDefKind::OptArg { .. }, Some(prefix)) |
+            (&ArgDefKind::Repeated { .. }, Some(prefix)) => {
+                let name = self.name.to_uppercase().replace('-', "_");
+                Some(format!("{}_{}", prefix, name).into())
+            }
+            _ => None,
+        }
+    }
+    
     /// Adds a help description for this argument.
     pub fn help<N>(mut self, help: N) -> Self where N: Into<Cow<'def, str>> {
         self.help_desc = Some(help.into());
@@ -0,0 +1,332 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+use help::{Help, OptHelp, HelpOptKind, trim_and_strip_lines};
+
+/// A shell that completion scripts can be generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Shell, String> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("Unknown shell '{}'. Expected bash, zsh or fish", s)),
+        }
+    }
+}
+
+/// Returns the first line of a help description.
+fn summary<'a, 'def>(help: &'a Option<Cow<'def, str>>) -> &'a str {
+    help.as_ref()
+        .and_then(|h| trim_and_strip_lines(h.as_ref()).next())
+        .unwrap_or("")
+}
+
+/// Returns the name of a shell function for the given command path.
+fn function_name(program: &str) -> String {
+    let words: Vec<_> = program.split_whitespace()
+        .map(|w| w.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect::<String>())
+        .collect();
+    format!("_{}", words.join("__"))
+}
+
+/// Returns the name of the given subcommand followed by its aliases.
+fn subcommand_names<'a, 'def>(help: &'a Help<'def>, name: &'a str) -> Vec<&'a str> {
+    let aliases = help.aliases.iter()
+        .filter(|&&(_, ref n)| n == name)
+        .map(|&(ref alias, _)| alias.as_ref());
+    Some(name).into_iter().chain(aliases).collect()
+}
+
+/// Collects this help and the help of every subcommand below it.
+fn walk<'a, 'def>(help: &'a Help<'def>, out: &mut Vec<&'a Help<'def>>) {
+    out.push(help);
+    for sub in help.subcommand_help.iter() {
+        walk(sub, out);
+    }
+}
+
+/// Returns how zsh completes a value: one of the choices, or a file.
+fn zsh_action<'def>(choices: &[Cow<'def, str>]) -> String {
+    if choices.is_empty() {
+        format!("_files")
+    } else {
+        format!("({})", sh_quote(&choices.join(" ")))
+    }
+}
+
+/// Quotes text for a single-quoted string in bash and zsh.
+fn sh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+}
+
+impl<'def> Help<'def> {
+    /// Generates a completion script for the given shell.
+    ///
+    /// Subcommands are only completed as far as their arguments are known, 
+    /// see `ArgDef::subcommand` and `ArgDef::described_by`.
+    pub fn completions(&self, shell: Shell) -> String {
+        match shell {
+            Shell::Bash => self.bash_completions(),
+            Shell::Zsh => self.zsh_completions(),
+            Shell::Fish => self.fish_completions(),
+        }
+    }
+    
+    fn bash_completions(&self) -> String {
+        let mut commands = Vec::new();
+        walk(self, &mut commands);
+        let func = function_name(&self.program);
+        let binary = self.program.split_whitespace().next().unwrap_or("");
+        
+        let mut s = String::new();
+        s.push_str(&format!("{}() {{\n", func));
+        s.push_str("    local cur prev cmd i\n");
+        s.push_str("    cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+        s.push_str("    prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+        s.push_str(&format!("    cmd=\"{}\"\n", self.program));
+        
+        if commands.iter().any(|c| ! c.subcommands.is_empty()) {
+            s.push_str("    for ((i = 1; i < COMP_CWORD; i++)); do\n");
+            s.push_str("        case \"${cmd}:${COMP_WORDS[i]}\" in\n");
+            for command in commands.iter() {
+                for &(ref name, _) in command.subcommands.iter() {
+                    let patterns: Vec<_> = subcommand_names(command, name).iter()
+                        .map(|n| format!("\"{}:{}\"", command.program, n))
+                        .collect();
+                    s.push_str(&format!("            {}) cmd=\"{} {}\" ;;\n",
+                        patterns.join("|"), command.program, name));
+                }
+            }
+            s.push_str("        esac\n");
+            s.push_str("    done\n");
+        }
+        
+        s.push_str("    case \"${cmd}\" in\n");
+        for command in commands.iter() {
+            s.push_str(&format!("        \"{}\")\n", command.program));
+            
+            let names = |o: &OptHelp| {
+                let mut names = vec![format!("--{}", o.name)];
+                if let Some(ref short) = o.short {
+                    names.push(format!("-{}", short));
+                }
+                names.join("|")
+            };
+            let valued: Vec<_> = command.options.iter()
+                .filter(|o| o.metavar.is_some() && o.choices.is_empty())
+                .map(|o| names(o))
+                .collect();
+            let chosen: Vec<_> = command.options.iter()
+                .filter(|o| o.metavar.is_some() && ! o.choices.is_empty())
+                .collect();
+            if ! (valued.is_empty() && chosen.is_empty()) {
+                s.push_str("            case \"${prev}\" in\n");
+                for opt in chosen {
+                    s.push_str(&format!("                {})\n", names(opt)));
+                    s.push_str(&format!("                    COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))\n", 
+                        opt.choices.join(" ")));
+                    s.push_str("                    return 0\n");
+                    s.push_str("                    ;;\n");
+                }
+                if ! valued.is_empty() {
+                    s.push_str(&format!("                {})\n", valued.join("|")));
+                    s.push_str("                    COMPREPLY=($(compgen -f -- \"${cur}\"))\n");
+                    s.push_str("                    return 0\n");
+                    s.push_str("                    ;;\n");
+                }
+                s.push_str("            esac\n");
+            }
+            
+            let mut words = Vec::new();
+            for opt in command.options.iter() {
+                words.push(format!("--{}", opt.name));
+                if opt.negatable {
+                    words.push(format!("--no-{}", opt.name));
+                }
+                if let Some(ref short) = opt.short {
+                    words.push(format!("-{}", short));
+                }
+            }
+            for &(ref name, _) in command.subcommands.iter() {
+                words.extend(subcommand_names(command, name).iter().map(|n| n.to_string()));
+            }
+            let words = words.join(" ");
+            
+            if command.positional.is_empty() && command.trail.is_none() {
+                s.push_str(&format!("            COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))\n", words));
+            } else {
+                s.push_str("            if [[ \"${cur}\" == -* ]]; then\n");
+                s.push_str(&format!("                COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))\n", words));
+                s.push_str("            else\n");
+                s.push_str("                COMPREPLY=($(compgen -f -- \"${cur}\"))\n");
+                s.push_str("            fi\n");
+            }
+            s.push_str("            ;;\n");
+        }
+        s.push_str("    esac\n");
+        s.push_str("}\n");
+        s.push_str(&format!("complete -F {} {}\n", func, binary));
+        s
+    }
+    
+    fn zsh_completions(&self) -> String {
+        let mut commands = Vec::new();
+        walk(self, &mut commands);
+        let binary = self.program.split_whitespace().next().unwrap_or("");
+        
+        let mut s = format!("#compdef {}\n", binary);
+        for command in commands.iter() {
+            let escape = |text: &str| {
+                sh_quote(text).replace('[', "\\[").replace(']', "\\]")
+            };
+            
+            s.push('\n');
+            s.push_str(&format!("{}() {{\n", function_name(&command.program)));
+            if ! command.subcommands.is_empty() {
+                s.push_str("    local line state\n");
+            }
+            s.push_str("    _arguments -C");
+            for opt in command.options.iter() {
+                let repeat = match opt.kind {
+                    HelpOptKind::Count | HelpOptKind::Repeated => true,
+                    _ => false,
+                };
+                let names = match opt.short {
+                    Some(ref short) if repeat => format!("'*'{{-{},--{}}}'", short, opt.name),
+                    Some(ref short) => format!("'(-{0} --{1})'{{-{0},--{1}}}'", short, opt.name),
+                    None if repeat => format!("'*--{}", opt.name),
+                    None => format!("'--{}", opt.name),
+                };
+                let value = match opt.metavar {
+                    Some(ref metavar) => format!(":{}:{}", escape(metavar), zsh_action(&opt.choices)),
+                    None => String::new(),
+                };
+                s.push_str(&format!(" \\\n        {}[{}]{}'",
+                    names, escape(summary(&opt.help)), value));
+                if opt.negatable {
+                    s.push_str(&format!(" \\\n        '--no-{}[{}]'", opt.name, escape(summary(&opt.help))));
+                }
+            }
+            for (i, pos) in command.positional.iter().enumerate() {
+                let optional = if pos.default.is_some() { ":" } else { "" };
+                s.push_str(&format!(" \\\n        '{}:{}{}:{}'", 
+                    i + 1, optional, escape(&pos.name), zsh_action(&pos.choices)));
+            }
+            if let Some((ref pos, _)) = command.trail {
+                s.push_str(&format!(" \\\n        '*:{}:{}'", escape(&pos.name), zsh_action(&pos.choices)));
+            }
+            if command.subcommands.is_empty() {
+                s.push('\n');
+            } else {
+                s.push_str(" \\\n        '1: :->cmds'");
+                s.push_str(" \\\n        '*::arg:->args' && return 0\n");
+                s.push_str("    case $state in\n");
+                s.push_str("        cmds)\n");
+                s.push_str("            local -a commands\n");
+                s.push_str("            commands=(\n");
+                for &(ref name, ref help) in command.subcommands.iter() {
+                    for n in subcommand_names(command, name) {
+                        s.push_str(&format!("                '{}:{}'\n",
+                            escape(n), escape(summary(help)).replace(':', "\\:")));
+                    }
+                }
+                s.push_str("            )\n");
+                s.push_str("            _describe -t commands 'command' commands\n");
+                s.push_str("            ;;\n");
+                s.push_str("        args)\n");
+                s.push_str("            case $line[1] in\n");
+                for &(ref name, _) in command.subcommands.iter() {
+                    let sub = format!("{} {}", command.program, name);
+                    if command.get_subcommand_help(name).is_some() {
+                        s.push_str(&format!("                {}) {} ;;\n", 
+                            subcommand_names(command, name).join("|"), function_name(&sub)));
+                    }
+                }
+                s.push_str("            esac\n");
+                s.push_str("            ;;\n");
+                s.push_str("    esac\n");
+            }
+            s.push_str("}\n");
+        }
+        s.push('\n');
+        s.push_str(&format!("{} \"$@\"\n", function_name(&self.program)));
+        s
+    }
+    
+    fn fish_completions(&self) -> String {
+        let mut commands = Vec::new();
+        walk(self, &mut commands);
+        let mut words = self.program.split_whitespace();
+        let binary = words.next().unwrap_or("");
+        let depth = words.count();
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('\'', "\\'");
+        
+        let mut s = String::new();
+        for command in commands.iter() {
+            // The subcommands that lead to this command from the generated one.
+            let path: Vec<_> = command.program.split_whitespace().skip(1 + depth).collect();
+            let mut conditions = Vec::new();
+            let mut parent: &Help<'def> = self;
+            for word in path.iter() {
+                conditions.push(format!("__fish_seen_subcommand_from {}", 
+                    subcommand_names(parent, word).join(" ")));
+                // INVARIANT: The path was built from the programs of the subcommands
+                parent = parent.get_subcommand_help(word).unwrap();
+            }
+            if ! command.subcommands.is_empty() {
+                if path.is_empty() {
+                    conditions.push(format!("__fish_use_subcommand"));
+                } else {
+                    let all: Vec<_> = command.subcommands.iter()
+                        .flat_map(|&(ref name, _)| subcommand_names(command, name))
+                        .collect();
+                    conditions.push(format!("not __fish_seen_subcommand_from {}", all.join(" ")));
+                }
+            }
+            let condition = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!(" -n '{}'", conditions.join("; and "))
+            };
+            
+            for &(ref name, ref help) in command.subcommands.iter() {
+                for n in subcommand_names(command, name) {
+                    s.push_str(&format!("complete -c {}{} -f -a '{}' -d '{}'\n",
+                        binary, condition, escape(n), escape(summary(help))));
+                }
+            }
+            for opt in command.options.iter() {
+                s.push_str(&format!("complete -c {}{}", binary, condition));
+                if let Some(ref short) = opt.short {
+                    if short.chars().count() == 1 {
+                        s.push_str(&format!(" -s {}", short));
+                    } else {
+                        s.push_str(&format!(" -o {}", short));
+                    }
+                }
+                s.push_str(&format!(" -l {}", opt.name));
+                if opt.metavar.is_some() {
+                    s.push_str(" -r");
+                }
+                if ! opt.choices.is_empty() {
+                    s.push_str(&format!(" -f -a '{}'", escape(&opt.choices.join(" "))));
+                }
+                s.push_str(&format!(" -d '{}'\n", escape(summary(&opt.help))));
+                if opt.negatable {
+                    s.push_str(&format!("complete -c {}{} -l no-{} -d '{}'\n", 
+                        binary, condition, opt.name, escape(summary(&opt.help))));
+                }
+            }
+        }
+        s
+    }
+}
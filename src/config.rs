@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// A value read from a config file.
+#[derive(Debug, Clone)]
+pub struct ConfigEntry {
+    pub key: String,
+    pub value: String,
+    /// The line the value was read from, starting at 1.
+    pub line: usize,
+}
+
+/// A simple INI-like config file, with `key = value` lines.
+///
+/// Keys are the names of the arguments. The keys before the first section
+/// belong to the program itself, and `[section]` headers select the
+/// subcommand the following keys belong to. Nested subcommands are separated
+/// by dots, like `[remote.add]`. Lines starting with `#` or `;` are comments.
+///
+/// # Example
+/// ```text
+/// verbose = true
+///
+/// [create]
+/// target_path = "out/book.epub"
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    /// The path of the file, as used in error messages.
+    pub path: String,
+    sections: HashMap<String, Vec<ConfigEntry>>,
+}
+
+/// An error from reading a config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file could not be read.
+    Io(String, io::Error),
+    /// A line of the file could not be read. (path, line, reason)
+    Syntax(String, usize, String),
+}
+
+impl ConfigFile {
+    /// Reads the config file at the given path.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ConfigFile, ConfigError> {
+        let name = path.as_ref().display().to_string();
+        let mut text = String::new();
+        match File::open(path.as_ref()).and_then(|mut file| file.read_to_string(&mut text)) {
+            Ok(_) => {}
+            Err(err) => return Err(ConfigError::Io(name, err)),
+        }
+        ConfigFile::from_str(name, &text)
+    }
+    
+    /// Reads a config file from the given text. The path is only used to
+    /// describe where values came from.
+    pub fn from_str<P: Into<String>>(path: P, text: &str) -> Result<ConfigFile, ConfigError> {
+        let path = path.into();
+        let mut sections = HashMap::new();
+        let mut section = String::new();
+        sections.insert(section.clone(), Vec::new());
+        
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") || line.starts_with(";") {
+                continue;
+            }
+            
+            if line.starts_with("[") {
+                if ! line.ends_with("]") {
+                    return Err(ConfigError::Syntax(path, line_no, format!("Unclosed section header")));
+                }
+                section = line[1..line.len() - 1].trim().to_string();
+                sections.entry(section.clone()).or_insert_with(Vec::new);
+                continue;
+            }
+            
+            let (key, value) = match line.find('=') {
+                Some(i) => (line[..i].trim(), unquote(line[i + 1..].trim())),
+                None => {
+                    return Err(ConfigError::Syntax(path, line_no, format!("Expected 'key = value'")));
+                }
+            };
+            if key.is_empty() {
+                return Err(ConfigError::Syntax(path, line_no, format!("Missing key before '='")));
+            }
+            
+            // INVARIANT: The current section is always inserted
+            let entries = sections.get_mut(&section).unwrap();
+            if entries.iter().any(|e: &ConfigEntry| e.key == key) {
+                return Err(ConfigError::Syntax(path, line_no, format!("Key '{}' given twice", key)));
+            }
+            entries.push(ConfigEntry {
+                key: key.to_string(),
+                value: value.to_string(),
+                line: line_no,
+            });
+        }
+        Ok(ConfigFile { path, sections })
+    }
+    
+    /// Returns the entries of the given section. The section of the program
+    /// itself is `""`.
+    pub fn section(&self, section: &str) -> &[ConfigEntry] {
+        self.sections.get(section).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+    
+    /// Finds the entry with the given key in the given section.
+    pub fn get(&self, section: &str, key: &str) -> Option<&ConfigEntry> {
+        self.section(section).iter().find(|e| e.key == key)
+    }
+}
+
+/// Removes a pair of surrounding double quotes from a value.
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with("\"") && value.ends_with("\"") {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+/// Returns the config section belonging to the given command path, like
+/// `remote.add` for `git remote add`.
+pub fn section_of(program: &str) -> String {
+    program.split_whitespace().skip(1).collect::<Vec<_>>().join(".")
+}
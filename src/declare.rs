@@ -0,0 +1,176 @@
+/// Declares a struct of parsed arguments, along with their definitions.
+///
+/// Every field is written as `name: Type [kind, modifiers...]`, where the
+/// kind is one of `pos`, `trail`, `optional_trail`, `passthrough`, `option`,
+/// `repeated`, `flag`, `tristate` and `count`, like the `ArgDef` 
+/// constructors, or one of `os_pos`, `os_trail`, `os_optional_trail`, 
+/// `os_option` and `os_repeated` for values read untouched, like paths. Each
+/// modifier calls the `ArgDef` method with that name, like `short = "v"`,
+/// `default = "out.txt"` or `required`. Field names are used as argument
+/// names, with `_` written as `-`, and the doc comments of the fields become
+/// their help.
+///
+/// The struct may be declared with any visibility, like `pub(crate)`, which
+/// its fields are given as well. It derives `Default`, and gets these
+/// methods:
+/// - `definitions(&mut self)`: The definitions, bound to the fields.
+/// - `parse_from(program, args)`: Parses the arguments into a new value,
+///   like `parse_plain`.
+///
+/// # Example
+/// ```
+/// # #[macro_use] extern crate playground;
+/// # use playground::*;
+/// args! {
+///     #[derive(Debug)]
+///     pub struct Options {
+///         /// Print more output.
+///         verbose: usize [count, short = "v"],
+///         /// Where to write the result.
+///         output: Option<String> [option, short = "o", default = "out.txt"],
+///         dry_run: bool [flag],
+///         /// The files to read.
+///         files: Vec<String> [trail],
+///     }
+/// }
+///
+/// # fn main() {
+/// let options = Options::parse_from("tool", &["-vv", "--dry-run", "a.txt"]).unwrap();
+/// assert_eq!(2, options.verbose);
+/// assert_eq!(Some("out.txt".to_string()), options.output);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! args {
+    // The visibility is matched by separate arms, since it is used again in
+    // the repetition of the fields.
+    (
+        $( #[$meta:meta] )*
+        pub $restrict:tt struct $name:ident {
+            $(
+                $( #[doc = $doc:expr] )*
+                $field:ident : $ty:ty [ $kind:ident $( , $modifier:ident $( = $value:expr )* )* ]
+            ),* $(,)*
+        }
+    ) => {
+        $( #[$meta] )*
+        #[derive(Default)]
+        pub $restrict struct $name {
+            $(
+                $( #[doc = $doc] )*
+                pub $restrict $field : $ty,
+            )*
+        }
+        
+        args!(@impl $name {
+            $(
+                $( #[doc = $doc] )*
+                $field : $ty [ $kind $( , $modifier $( = $value )* )* ]
+            ),*
+        });
+    };
+    
+    (
+        $( #[$meta:meta] )*
+        pub struct $name:ident {
+            $(
+                $( #[doc = $doc:expr] )*
+                $field:ident : $ty:ty [ $kind:ident $( , $modifier:ident $( = $value:expr )* )* ]
+            ),* $(,)*
+        }
+    ) => {
+        $( #[$meta] )*
+        #[derive(Default)]
+        pub struct $name {
+            $(
+                $( #[doc = $doc] )*
+                pub $field : $ty,
+            )*
+        }
+        
+        args!(@impl $name {
+            $(
+                $( #[doc = $doc] )*
+                $field : $ty [ $kind $( , $modifier $( = $value )* )* ]
+            ),*
+        });
+    };
+    
+    (
+        $( #[$meta:meta] )*
+        struct $name:ident {
+            $(
+                $( #[doc = $doc:expr] )*
+                $field:ident : $ty:ty [ $kind:ident $( , $modifier:ident $( = $value:expr )* )* ]
+            ),* $(,)*
+        }
+    ) => {
+        $( #[$meta] )*
+        #[derive(Default)]
+        struct $name {
+            $(
+                $( #[doc = $doc] )*
+                $field : $ty,
+            )*
+        }
+        
+        args!(@impl $name {
+            $(
+                $( #[doc = $doc] )*
+                $field : $ty [ $kind $( , $modifier $( = $value )* )* ]
+            ),*
+        });
+    };
+    
+    // The methods of the struct.
+    (@impl $name:ident {
+        $(
+            $( #[doc = $doc:expr] )*
+            $field:ident : $ty:ty [ $kind:ident $( , $modifier:ident $( = $value:expr )* )* ]
+        ),* $(,)*
+    }) => {
+        impl $name {
+            /// Returns the argument definitions, bound to the fields of this
+            /// value `[macro-generated]`.
+            pub fn definitions<'def, 'tar>(&'tar mut self) -> Vec<$crate::ArgDef<'def, 'tar>> {
+                vec![ $(
+                    {
+                        let name = stringify!($field).replace('_', "-");
+                        let mut def = args!(@def $kind, name, &mut self.$field);
+                        let doc: &'static str = concat!( $( $doc, "\n", )* );
+                        if ! doc.is_empty() {
+                            def.help_desc = Some(doc.into());
+                        }
+                        def $( .$modifier( $( $value ),* ) )*
+                    }
+                ),* ]
+            }
+            
+            /// Parses the given arguments into a new value, like `parse_plain`
+            /// `[macro-generated]`.
+            pub fn parse_from<'def, P, A>(program: P, args: &[A]) -> Result<$name, $crate::ParseError<'def>>
+              where P: Into<String>, A: ::std::borrow::Borrow<str>
+            {
+                let mut parsed = <$name as Default>::default();
+                $crate::parse_plain(program, args, parsed.definitions())?;
+                Ok(parsed)
+            }
+        }
+    };
+    
+    // The constructor of each kind.
+    (@def pos, $name:expr, $target:expr) => { $crate::ArgDef::pos($name, $target) };
+    (@def trail, $name:expr, $target:expr) => { $crate::ArgDef::trail($name, false, $target) };
+    (@def optional_trail, $name:expr, $target:expr) => { $crate::ArgDef::trail($name, true, $target) };
+    (@def passthrough, $name:expr, $target:expr) => { $crate::ArgDef::passthrough($name, $target) };
+    (@def option, $name:expr, $target:expr) => { $crate::ArgDef::option($name, $target) };
+    (@def repeated, $name:expr, $target:expr) => { $crate::ArgDef::repeated($name, $target) };
+    (@def flag, $name:expr, $target:expr) => { $crate::ArgDef::flag($name, $target) };
+    (@def tristate, $name:expr, $target:expr) => { $crate::ArgDef::tristate($name, $target) };
+    (@def count, $name:expr, $target:expr) => { $crate::ArgDef::count($name, $target) };
+    (@def os_pos, $name:expr, $target:expr) => { $crate::ArgDef::os_pos($name, $target) };
+    (@def os_trail, $name:expr, $target:expr) => { $crate::ArgDef::os_trail($name, false, $target) };
+    (@def os_optional_trail, $name:expr, $target:expr) => { $crate::ArgDef::os_trail($name, true, $target) };
+    (@def os_option, $name:expr, $target:expr) => { $crate::ArgDef::os_option($name, $target) };
+    (@def os_repeated, $name:expr, $target:expr) => { $crate::ArgDef::os_repeated($name, $target) };
+}
@@ -0,0 +1,419 @@
+use std::borrow::Cow;
+use help::{Help, PosHelp, trim_and_strip_lines};
+
+/// Escapes text for use in a roff document.
+fn roff_escape(text: &str) -> String {
+    let text = text.replace('\\', "\\\\").replace('-', "\\-");
+    if text.starts_with(".") || text.starts_with("'") {
+        format!("\\&{}", text)
+    } else {
+        text
+    }
+}
+
+/// Writes the trimmed lines of a text as roff, with blank lines as paragraph
+/// breaks.
+fn write_roff_text(s: &mut String, text: &str) {
+    for line in trim_and_strip_lines(text) {
+        if line.is_empty() {
+            s.push_str(".PP\n");
+        } else {
+            s.push_str(&roff_escape(line));
+            s.push('\n');
+        }
+    }
+}
+
+/// Joins the trimmed lines of a help description into a single line.
+fn one_line<'def>(help: &Option<Cow<'def, str>>) -> String {
+    match *help {
+        Some(ref help) => {
+            trim_and_strip_lines(help).filter(|line| ! line.is_empty()).collect::<Vec<_>>().join(" ")
+        }
+        None => String::new(),
+    }
+}
+
+/// Returns how the trail is written, like `[files...]`.
+fn trail_signature(name: &str, optional: bool) -> String {
+    if optional {
+        format!("[{}...]", name)
+    } else {
+        format!("{} [{}...]", name, name)
+    }
+}
+
+impl<'def> Help<'def> {
+    /// Returns the positional arguments, the trail and the passthrough 
+    /// argument, along with how they are written.
+    pub fn positional_signatures(&self) -> Vec<(&PosHelp<'def>, String)> {
+        let mut signatures: Vec<_> = self.positional.iter()
+            .map(|pos| (pos, pos.name.to_string()))
+            .collect();
+        if let Some((ref pos, optional)) = self.trail {
+            signatures.push((pos, trail_signature(&pos.name, optional || pos.default.is_some())));
+        }
+        if let Some(ref pos) = self.passthrough {
+            signatures.push((pos, pos.passthrough_signature()));
+        }
+        signatures
+    }
+    
+    /// Generates a section 1 man page in roff format, using the given program
+    /// description. The description may be left blank.
+    pub fn man_page(&self, description: &str) -> String {
+        let title = self.program.split_whitespace().collect::<Vec<_>>().join("-");
+        let summary = trim_and_strip_lines(description).next().unwrap_or("");
+        
+        let mut s = format!(".TH {} 1\n", roff_escape(&title.to_uppercase()));
+        s.push_str(".SH NAME\n");
+        if summary.is_empty() {
+            s.push_str(&format!("{}\n", roff_escape(&title)));
+        } else {
+            s.push_str(&format!("{} \\- {}\n", roff_escape(&title), roff_escape(summary)));
+        }
+        
+        s.push_str(".SH SYNOPSIS\n");
+        let mut usage = String::new();
+        self.write_usage_into(&mut usage);
+        let args = usage[self.program.len()..].trim();
+        s.push_str(&format!(".B {}\n", roff_escape(&self.program)));
+        if ! args.is_empty() {
+            s.push_str(&format!("{}\n", roff_escape(args)));
+        }
+        
+        if ! description.trim().is_empty() {
+            s.push_str(".SH DESCRIPTION\n");
+            write_roff_text(&mut s, description);
+        }
+        
+        if ! self.positional.is_empty() || self.trail.is_some() || self.passthrough.is_some() {
+            s.push_str(".SH ARGUMENTS\n");
+            for (pos, signature) in self.positional_signatures() {
+                let mut notes = String::new();
+                pos.write_annotations_into(&mut notes);
+                s.push_str(".TP\n");
+                s.push_str(&format!(".B {}\n", roff_escape(&signature)));
+                if ! notes.is_empty() {
+                    s.push_str(&format!("{}\n", roff_escape(notes.trim())));
+                    s.push_str(".br\n");
+                }
+                if let Some(ref help) = pos.help {
+                    write_roff_text(&mut s, help);
+                }
+            }
+        }
+        
+        if ! self.options.is_empty() {
+            s.push_str(".SH OPTIONS\n");
+            for opt in self.options.iter() {
+                let mut notes = String::new();
+                opt.write_annotations_into(&mut notes);
+                s.push_str(".TP\n");
+                s.push_str(&format!(".B {}\n", roff_escape(&opt.signature())));
+                if ! notes.is_empty() {
+                    s.push_str(&format!("{}\n", roff_escape(notes.trim())));
+                    s.push_str(".br\n");
+                }
+                if let Some(ref help) = opt.help {
+                    write_roff_text(&mut s, help);
+                }
+            }
+        }
+        
+        if ! self.subcommands.is_empty() {
+            s.push_str(".SH COMMANDS\n");
+            for &(ref name, ref help) in self.subcommands.iter() {
+                s.push_str(".TP\n");
+                s.push_str(&format!(".B {}\n", roff_escape(name)));
+                if let Some(ref help) = *help {
+                    write_roff_text(&mut s, help);
+                }
+            }
+        }
+        s
+    }
+    
+    /// Generates a Markdown reference for this program, using the given
+    /// program description. The description may be left blank.
+    ///
+    /// Subcommands whose arguments are known get a section of their own, see
+    /// `ArgDef::subcommand` and `ArgDef::described_by`.
+    pub fn markdown(&self, description: &str) -> String {
+        let mut s = String::new();
+        self.write_markdown_into(&mut s, description, 1);
+        s
+    }
+    
+    fn write_markdown_into(&self, s: &mut String, description: &str, level: usize) {
+        let heading = "#".repeat(level);
+        s.push_str(&format!("{} {}\n\n", heading, self.program));
+        
+        let mut wrote_description = false;
+        for line in trim_and_strip_lines(description) {
+            s.push_str(line);
+            s.push('\n');
+            wrote_description = true;
+        }
+        if wrote_description {
+            s.push('\n');
+        }
+        
+        s.push_str(&format!("{}# Usage\n\n```text\n", heading));
+        self.write_usage_into(s);
+        s.push_str("\n```\n\n");
+        
+        if ! self.positional.is_empty() || self.trail.is_some() || self.passthrough.is_some() {
+            s.push_str(&format!("{}# Arguments\n\n", heading));
+            for (pos, signature) in self.positional_signatures() {
+                s.push_str(&format!("- `{}`", signature));
+                pos.write_annotations_into(s);
+                let help = one_line(&pos.help);
+                if ! help.is_empty() {
+                    s.push_str(": ");
+                    s.push_str(&help);
+                }
+                s.push('\n');
+            }
+            s.push('\n');
+        }
+        
+        if ! self.options.is_empty() {
+            s.push_str(&format!("{}# Options\n\n", heading));
+            for opt in self.options.iter() {
+                s.push_str(&format!("- `{}`", opt.signature()));
+                opt.write_annotations_into(s);
+                let help = one_line(&opt.help);
+                if ! help.is_empty() {
+                    s.push_str(": ");
+                    s.push_str(&help);
+                }
+                s.push('\n');
+            }
+            s.push('\n');
+        }
+        
+        if ! self.subcommands.is_empty() {
+            s.push_str(&format!("{}# Commands\n\n", heading));
+            for &(ref name, ref help) in self.subcommands.iter() {
+                s.push_str(&format!("- `{}`", name));
+                let help = one_line(help);
+                if ! help.is_empty() {
+                    s.push_str(": ");
+                    s.push_str(&help);
+                }
+                s.push('\n');
+            }
+            s.push('\n');
+            
+            for &(ref name, ref help) in self.subcommands.iter() {
+                if let Some(sub) = self.get_subcommand_help(name) {
+                    let description = help.as_ref().map(|h| h.as_ref()).unwrap_or("");
+                    sub.write_markdown_into(s, description, level + 1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use argdef::ArgDef;
+    use help::Help;
+    use parse::parse_definitions;
+    use settings::ParseSettings;
+    
+    const DESCRIPTION: &'static str = "
+        Does things to files.
+        
+        .Starts with a dot.
+    ";
+    
+    /// Returns the help of the given definitions, which must be valid so 
+    /// that the snapshots show a program that can be parsed.
+    fn checked(definitions: Vec<ArgDef<'static, 'static>>) -> Help<'static> {
+        let help = Help::new("tool".to_string(), &definitions);
+        parse_definitions(definitions, &ParseSettings::new()).unwrap();
+        help
+    }
+    
+    /// A program that reads positional arguments.
+    fn positional_help() -> Help<'static> {
+        let definitions = vec![
+            ArgDef::unbound_pos("input").help("
+                The file to read.
+                A \\ is kept.
+            "),
+            ArgDef::unbound_trail("extra", true).help("More files."),
+            ArgDef::unbound_flag("dry-run").short("n").help("
+                .Only pretend.
+                
+                Nothing is written.
+            "),
+            ArgDef::unbound_option("level").default("1").help("The log level."),
+        ];
+        checked(definitions)
+    }
+    
+    /// A program with subcommands, one of which has arguments of its own.
+    fn subcommand_help() -> Help<'static> {
+        let definitions = vec![
+            ArgDef::unbound_flag("verbose").short("v").global().help("Print more."),
+            ArgDef::subcommand("sync", vec![
+                ArgDef::unbound_flag("all").help("Sync everything."),
+            ]).help("Syncs the files - all of them."),
+            ArgDef::subcommand("status", vec![]).help("Shows the status."),
+        ];
+        checked(definitions)
+    }
+    
+    #[test]
+    fn positional_man_page() {
+        let expected = r#".TH TOOL 1
+.SH NAME
+tool \- Does things to files.
+.SH SYNOPSIS
+.B tool
+[ OPTIONS ] input [extra...]
+.SH DESCRIPTION
+Does things to files.
+.PP
+\&.Starts with a dot.
+.SH ARGUMENTS
+.TP
+.B input
+The file to read.
+A \\ is kept.
+.TP
+.B [extra...]
+More files.
+.SH OPTIONS
+.TP
+.B \-\-dry\-run, \-n
+\&.Only pretend.
+.PP
+Nothing is written.
+.TP
+.B \-\-level LEVEL
+[default: 1]
+.br
+The log level.
+"#;
+        assert_eq!(expected, positional_help().man_page(DESCRIPTION));
+    }
+    
+    #[test]
+    fn positional_markdown() {
+        let expected = r#"# tool
+
+Does things to files.
+
+.Starts with a dot.
+
+## Usage
+
+```text
+tool [ OPTIONS ] input [extra...]
+```
+
+## Arguments
+
+- `input`: The file to read. A \ is kept.
+- `[extra...]`: More files.
+
+## Options
+
+- `--dry-run, -n`: .Only pretend. Nothing is written.
+- `--level LEVEL` [default: 1]: The log level.
+
+"#;
+        assert_eq!(expected, positional_help().markdown(DESCRIPTION));
+    }
+    
+    #[test]
+    fn subcommand_man_page() {
+        let expected = r#".TH TOOL 1
+.SH NAME
+tool \- Does things to files.
+.SH SYNOPSIS
+.B tool
+[ OPTIONS ] { sync | status | help }
+.SH DESCRIPTION
+Does things to files.
+.PP
+\&.Starts with a dot.
+.SH OPTIONS
+.TP
+.B \-\-verbose, \-v
+Print more.
+.SH COMMANDS
+.TP
+.B sync
+Syncs the files \- all of them.
+.TP
+.B status
+Shows the status.
+.TP
+.B help
+Print the help of the given subcommand.
+"#;
+        assert_eq!(expected, subcommand_help().man_page(DESCRIPTION));
+    }
+    
+    #[test]
+    fn subcommand_markdown() {
+        let expected = r#"# tool
+
+Does things to files.
+
+.Starts with a dot.
+
+## Usage
+
+```text
+tool [ OPTIONS ] { sync | status | help }
+```
+
+## Options
+
+- `--verbose, -v`: Print more.
+
+## Commands
+
+- `sync`: Syncs the files - all of them.
+- `status`: Shows the status.
+- `help`: Print the help of the given subcommand.
+
+## tool sync
+
+Syncs the files - all of them.
+
+### Usage
+
+```text
+tool sync [ OPTIONS ]
+```
+
+### Options
+
+- `--all`: Sync everything.
+- `--verbose, -v`: Print more.
+
+## tool status
+
+Shows the status.
+
+### Usage
+
+```text
+tool status [ OPTIONS ]
+```
+
+### Options
+
+- `--verbose, -v`: Print more.
+
+"#;
+        assert_eq!(expected, subcommand_help().markdown(DESCRIPTION));
+    }
+}
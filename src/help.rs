@@ -1,5 +1,7 @@
 use std::borrow::Cow;
 use argdef::{ArgDef, ArgDefKind};
+use settings::ParseSettings;
+use style::{Style, Sink};
 use std_unicode::str::UnicodeStr;
 
 pub fn trim_and_strip_lines<'a>(text: &'a str) -> impl Iterator<Item=&'a str> {
@@ -20,74 +22,282 @@ fn write_trimmed_n<'def, T: AsRef<str>>(s: &mut String, prefix: &str, text: T) {
 
 
 /// A collection of descriptions of the defined arguments.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Help<'def> {
     /// The 'command path' of the run program, eg. `cargo` or `cargo new`.
     pub program: String,
     /// Positional arguments.
-    pub positional: Vec<(Cow<'def, str>, Option<Cow<'def, str>>)>,
-    /// Trailing positional vararg.
-    pub trail: Option<(Cow<'def, str>, bool, Option<Cow<'def, str>>)>,
+    pub positional: Vec<PosHelp<'def>>,
+    /// Trailing positional vararg, and whether it is optional.
+    pub trail: Option<(PosHelp<'def>, bool)>,
+    /// The arguments passed through after `--`.
+    pub passthrough: Option<PosHelp<'def>>,
     /// Subcommand arguments.
     pub subcommands: Vec<(Cow<'def, str>, Option<Cow<'def, str>>)>,
+    /// The aliases of the subcommands. (alias, name)
+    pub aliases: Vec<(Cow<'def, str>, Cow<'def, str>)>,
     /// Optional arguments.
-    pub options: Vec<(Cow<'def, str>, Option<Cow<'def, str>>, HelpOptKind, Option<Cow<'def, str>>)>,
+    pub options: Vec<OptHelp<'def>>,
+    /// Groups of arguments.
+    pub groups: Vec<GroupHelp<'def>>,
     /// Is `--help` defined.
     pub help_defined: bool,
+    /// The help of each subcommand whose arguments are known.
+    /// See `ArgDef::subcommand` and `ArgDef::described_by`.
+    pub subcommand_help: Vec<Help<'static>>,
+}
+
+/// The description of a positional argument or a trail.
+#[derive(Debug, Clone)]
+pub struct PosHelp<'def> {
+    pub name: Cow<'def, str>,
+    pub help: Option<Cow<'def, str>>,
+    /// The environment variable read when the argument is missing.
+    pub env: Option<Cow<'def, str>>,
+    /// The value used when the argument is missing.
+    pub default: Option<Cow<'def, str>>,
+    /// The only values accepted, unless empty.
+    pub choices: Vec<Cow<'def, str>>,
+    /// The arguments that must be given along with this one, as written in
+    /// the usage, like `--cert` for an option.
+    pub requires: Vec<Cow<'def, str>>,
+    /// The arguments that can't be given along with this one.
+    pub conflicts: Vec<Cow<'def, str>>,
+}
+
+/// The description of a group of arguments.
+#[derive(Debug, Clone)]
+pub struct GroupHelp<'def> {
+    pub name: Cow<'def, str>,
+    pub members: Vec<Cow<'def, str>>,
+    /// Whether at most one member may be given.
+    pub exclusive: bool,
+    /// Whether at least one member must be given.
+    pub required: bool,
+}
+
+/// The description of an optional argument.
+#[derive(Debug, Clone)]
+pub struct OptHelp<'def> {
+    pub name: Cow<'def, str>,
+    pub short: Option<Cow<'def, str>>,
+    pub kind: HelpOptKind,
+    /// The name of the value the option takes, if any.
+    pub metavar: Option<Cow<'def, str>>,
+    pub help: Option<Cow<'def, str>>,
+    /// The environment variable read when the option is missing.
+    pub env: Option<Cow<'def, str>>,
+    /// The value used when the option is missing.
+    pub default: Option<Cow<'def, str>>,
+    /// The only values accepted, unless empty.
+    pub choices: Vec<Cow<'def, str>>,
+    /// Whether the option must be given.
+    pub required: bool,
+    /// Whether the option is accepted by the subcommands as well.
+    pub global: bool,
+    /// Whether the flag can be turned off with `--no-<name>`.
+    pub negatable: bool,
+    /// The arguments that must be given along with this one, as written in
+    /// the usage, like `--cert` for an option.
+    pub requires: Vec<Cow<'def, str>>,
+    /// The arguments that can't be given along with this one.
+    pub conflicts: Vec<Cow<'def, str>>,
 }
 
 impl<'def> Help<'def> {
     /// Creates a new help object from the given descriptions.
     pub fn new<'tar>(program: String, definitions: &[ArgDef<'def, 'tar>]) -> Help<'def> {
+        Help::with_settings(program, definitions, &ParseSettings::new())
+    }
+    
+    /// Creates a new help object from the given descriptions, as they are
+    /// read with the given settings.
+    pub fn with_settings<'tar>(program: String, definitions: &[ArgDef<'def, 'tar>], 
+            settings: &ParseSettings) -> Help<'def> {
+        Help::with_inherited(program, definitions, settings, &[])
+    }
+    
+    /// Like `with_settings`, for a subcommand that also accepts the given 
+    /// global options of the commands above it.
+    ///
+    /// The help of the subcommands defined with `ArgDef::subcommand`, or 
+    /// described with `ArgDef::described_by`, is added to `subcommand_help`.
+    pub fn with_inherited<'tar>(program: String, definitions: &[ArgDef<'def, 'tar>], 
+            settings: &ParseSettings, inherited: &[OptHelp<'def>]) -> Help<'def> {
         let mut positional = Vec::new();
         let mut trail = None;
+        let mut passthrough = None;
         let mut options = Vec::new();
+        let mut groups = Vec::new();
         let mut subcommands = Vec::new();
+        let mut commands = Vec::new();
+        let mut aliases = Vec::new();
         let mut help_defined = false;
         for def in definitions {
-            match def.kind {
+            if def.hidden {
+                continue;
+            }
+            let env = def.env_var(settings.env_prefix.as_ref().map(|p| p.as_str()));
+            let mut metavar = None;
+            let mut required = false;
+            let mut negatable = false;
+            let (short, kind) = match def.kind {
                 ArgDefKind::Positional { .. } => {
-                    positional.push((def.name.clone(), def.help_desc.clone()));
+                    positional.push(PosHelp::new(def, env, definitions));
+                    continue;
                 }
                 ArgDefKind::Trail { optional, .. } => {
-                    trail = Some((def.name.clone(), optional, def.help_desc.clone()));
+                    trail = Some((PosHelp::new(def, env, definitions), optional));
+                    continue;
                 },
-                ArgDefKind::Subcommand { .. } => {
+                ArgDefKind::Passthrough { .. } => {
+                    passthrough = Some(PosHelp::new(def, env, definitions));
+                    continue;
+                }
+                ArgDefKind::Group { ref members, exclusive, required } => {
+                    groups.push(GroupHelp { 
+                        name: def.name.clone(), 
+                        members: members.clone(), 
+                        exclusive, 
+                        required,
+                    });
+                    continue;
+                }
+                ArgDefKind::Subcommand { ref described, .. } => {
+                    subcommands.push((def.name.clone(), def.help_desc.clone()));
+                    aliases.extend(def.aliases.iter().map(|alias| (alias.clone(), def.name.clone())));
+                    // The handler parses its arguments by itself, without 
+                    // the global options.
+                    if ! described.is_empty() {
+                        commands.push((&def.name, described, false));
+                    }
+                    continue;
+                }
+                ArgDefKind::Command { ref children } => {
                     subcommands.push((def.name.clone(), def.help_desc.clone()));
+                    aliases.extend(def.aliases.iter().map(|alias| (alias.clone(), def.name.clone())));
+                    commands.push((&def.name, children, true));
+                    continue;
+                }
+                ArgDefKind::Flag { ref short, negatable: is_negatable, .. } => {
+                    negatable = is_negatable;
+                    (short, HelpOptKind::Flag)
+                }
+                ArgDefKind::Tristate { ref short, .. } => {
+                    negatable = true;
+                    (short, HelpOptKind::Flag)
+                }
+                ArgDefKind::Count { ref short, .. } => (short, HelpOptKind::Count),
+                ArgDefKind::OptArg { ref short, required: is_required, .. } => {
+                    metavar = Some(value_name(def));
+                    required = is_required;
+                    (short, HelpOptKind::OptArg)
                 }
-                ArgDefKind::Flag { ref short, .. } => {
-                    options.push((
-                        def.name.clone(), short.clone(), 
-                        HelpOptKind::Flag, def.help_desc.clone()
-                    ));
-                }
-                ArgDefKind::Count { ref short, .. } => {
-                    options.push((
-                        def.name.clone(), short.clone(), 
-                        HelpOptKind::Count, def.help_desc.clone()
-                    ));
-                }
-                ArgDefKind::OptArg { ref short, .. } => {
-                    options.push((
-                        def.name.clone(), short.clone(), 
-                        HelpOptKind::OptArg, def.help_desc.clone()
-                    ));
-                }
-                ArgDefKind::Interrupt { ref short, .. } => {
+                ArgDefKind::Repeated { ref short, required: is_required, .. } => {
+                    metavar = Some(value_name(def));
+                    required = is_required;
+                    (short, HelpOptKind::Repeated)
+                }
+                ArgDefKind::Interrupt { ref short, ref value_name, .. } => {
                     if def.name.as_ref() == "help" {
                         help_defined = true;
                     }
-                    options.push((
-                        def.name.clone(), short.clone(), 
-                        HelpOptKind::Interrupt, def.help_desc.clone()
-                    ));
+                    metavar = value_name.clone();
+                    (short, HelpOptKind::Interrupt)
                 }
+            };
+            options.push(OptHelp {
+                name: def.name.clone(),
+                short: short.clone(),
+                kind,
+                metavar,
+                help: def.help_desc.clone(),
+                env,
+                default: def.default.clone(),
+                choices: def.choices.clone(),
+                required,
+                global: def.global,
+                negatable,
+                requires: relation_names(&def.requires, definitions),
+                conflicts: relation_names(&def.conflicts, definitions),
+            });
+        }
+        for opt in inherited.iter() {
+            if options.iter().any(|o: &OptHelp| o.name == opt.name) {
+                continue;
             }
+            if let (HelpOptKind::Interrupt, "help") = (opt.kind, opt.name.as_ref()) {
+                help_defined = true;
+            }
+            options.push(opt.clone());
+        }
+        // A `help` subcommand is provided unless one is defined already.
+        let help_taken = definitions.iter().any(|def| match def.kind {
+            ArgDefKind::Subcommand { .. } | ArgDefKind::Command { .. } => {
+                def.name == "help" || def.aliases.iter().any(|alias| alias == "help")
+            }
+            _ => false,
+        });
+        if ! (subcommands.is_empty() || help_taken) {
+            let desc = "Print the help of the given subcommand.";
+            subcommands.push((Cow::Borrowed("help"), Some(Cow::Borrowed(desc))));
+        }
+        let globals: Vec<_> = options.iter().filter(|o| o.global).cloned().collect();
+        let subcommand_help = commands.into_iter()
+            .map(|(name, children, inherits)| {
+                let subprogram = format!("{} {}", program, name);
+                let inherited = if inherits { &globals[..] } else { &[] };
+                Help::with_inherited(subprogram, children, settings, inherited).to_static()
+            })
+            .collect();
+        Help { 
+            program, positional, trail, passthrough, subcommands, aliases, options, groups, 
+            help_defined,
+            subcommand_help,
         }
-        Help { program, positional, trail, subcommands, options, help_defined }
     }
     
-    fn write_usage_into(&self, s: &mut String) {
+    /// Copies this help, so that it no longer borrows the definitions.
+    pub fn to_static(&self) -> Help<'static> {
+        Help {
+            program: self.program.clone(),
+            positional: self.positional.iter().map(|p| p.to_static()).collect(),
+            trail: self.trail.as_ref().map(|&(ref p, optional)| (p.to_static(), optional)),
+            passthrough: self.passthrough.as_ref().map(|p| p.to_static()),
+            subcommands: self.subcommands.iter()
+                .map(|&(ref name, ref help)| (own(name), help.as_ref().map(own)))
+                .collect(),
+            aliases: self.aliases.iter()
+                .map(|&(ref alias, ref name)| (own(alias), own(name)))
+                .collect(),
+            options: self.options.iter().map(|o| o.to_static()).collect(),
+            groups: self.groups.iter().map(|g| GroupHelp {
+                name: own(&g.name),
+                members: g.members.iter().map(own).collect(),
+                exclusive: g.exclusive,
+                required: g.required,
+            }).collect(),
+            help_defined: self.help_defined,
+            subcommand_help: self.subcommand_help.clone(),
+        }
+    }
+    
+    /// Returns the name of the subcommand with the given name or alias.
+    pub fn resolve_alias<'a>(&'a self, name: &'a str) -> &'a str {
+        match self.aliases.iter().find(|&&(ref alias, _)| alias == name) {
+            Some(&(_, ref name)) => name,
+            None => name,
+        }
+    }
+    
+    /// Finds the help of the given subcommand, if its arguments are known.
+    pub fn get_subcommand_help(&self, name: &str) -> Option<&Help<'static>> {
+        let program = format!("{} {}", self.program, self.resolve_alias(name));
+        self.subcommand_help.iter().find(|h| h.program == program)
+    }
+    
+    /// Writes the usage line of this program, starting with its command path.
+    pub fn write_usage_into(&self, s: &mut String) {
         s.push_str(&self.program);
         
         if ! self.options.is_empty() {
@@ -102,14 +312,47 @@ impl<'def> Help<'def> {
             }
         }
         
-        for &(ref name, _) in self.positional.iter() {
+        // Required and repeated options are shown by themselves, unless a 
+        // group shows them already.
+        for opt in self.options.iter() {
+            let repeated = match opt.kind {
+                HelpOptKind::Repeated => true,
+                _ => false,
+            };
+            let grouped = self.groups.iter()
+                .any(|g| (g.exclusive || g.required) && g.members.contains(&opt.name));
+            if grouped {
+                continue;
+            }
+            if opt.required {
+                s.push_str(&format!(" {}", self.usage_name(&opt.name)));
+            } else if repeated {
+                s.push_str(&format!(" [{}]", self.usage_name(&opt.name)));
+            }
+        }
+        
+        for group in self.groups.iter() {
+            if ! (group.exclusive || group.required) {
+                continue;
+            }
+            let members: Vec<_> = group.members.iter()
+                .map(|name| self.usage_name(name))
+                .collect();
+            s.push_str(&format!(" ( {} )", members.join(" | ")));
+        }
+        
+        for pos in self.positional.iter() {
             s.push(' ');
-            s.push_str(name.as_ref());
+            if pos.default.is_some() {
+                s.push_str(&format!("[{}]", pos.name));
+            } else {
+                s.push_str(pos.name.as_ref());
+            }
         }
         
-        if let Some((ref name, optional, _)) = self.trail {
+        if let Some((PosHelp { ref name, ref default, .. }, optional)) = self.trail {
             s.push(' ');
-            if optional {
+            if optional || default.is_some() {
                 s.push_str(&format!("[{}...]", name));
             } else {
                 s.push_str(&format!("{} [{}...]", name, name));
@@ -127,6 +370,23 @@ impl<'def> Help<'def> {
             }
             s.push_str(" }");
         }
+        
+        if let Some(ref pos) = self.passthrough {
+            s.push(' ');
+            s.push_str(&pos.passthrough_signature());
+        }
+    }
+    
+    /// Returns how the named argument is written in the usage line, like 
+    /// `--output OUTPUT` for an option or `file` for a positional argument.
+    pub fn usage_name(&self, name: &str) -> String {
+        match self.options.iter().find(|o| o.name == name) {
+            Some(opt) => match opt.value_signature() {
+                Some(value) => format!("--{} {}", name, value),
+                None => format!("--{}", name),
+            },
+            None => name.to_string(),
+        }
     }
     
     /// Generates a usage message for this program.
@@ -136,19 +396,33 @@ impl<'def> Help<'def> {
         s
     }
     
-    /// Prints a usage message for this program.
+    /// Prints a usage message for this program to stdout.
     pub fn print_usage(&self) {
-        println!("Usage: {}", self.usage_message());
+        self.write_usage_to(&mut Sink::stdout());
+    }
+    
+    /// Writes a usage message for this program to the given sink, in its
+    /// style.
+    pub fn write_usage_to(&self, sink: &mut Sink) {
+        let line = format!("{} {}\n", sink.style.header("Usage:"), self.usage_message());
+        sink.write_str(&line);
     }
     
     /// Generates a help message for this program, using the given program
     /// description. The description may be left blank.
     pub fn help_message(&self, description: &str) -> String {
-        let mut s = String::from("Usage:\n  ");
+        self.styled_help_message(description, Style::plain())
+    }
+    
+    /// Like `help_message`, with bold section headers and colored names in
+    /// the given style.
+    pub fn styled_help_message(&self, description: &str, style: Style) -> String {
+        let mut s = format!("{}\n  ", style.header("Usage:"));
         self.write_usage_into(&mut s);
         
         let has_description = description != "";
-        let has_positional = (! self.positional.is_empty()) || self.trail.is_some();
+        let has_positional = (! self.positional.is_empty()) || self.trail.is_some() 
+            || self.passthrough.is_some();
         let has_optional = ! self.options.is_empty();
         let has_subcommands = ! self.subcommands.is_empty();
         if has_positional || has_optional || has_description || has_subcommands {
@@ -156,28 +430,42 @@ impl<'def> Help<'def> {
         }
         
         if has_description {
-            s.push_str("Description:\n");
+            s.push_str(&style.header("Description:"));
+            s.push('\n');
             write_trimmed_n(&mut s, "  ", description);
         }
         
         if has_positional {
             s.push('\n');
-            s.push_str("Positional arguments:\n");
-            for &(ref name, ref help) in self.positional.iter() {
-                s.push_str(&format!("  {}\n", name));
-                if let &Some(ref help) = help {
+            s.push_str(&style.header("Positional arguments:"));
+            s.push('\n');
+            for pos in self.positional.iter() {
+                s.push_str(&format!("  {}", style.name(&pos.name)));
+                pos.write_annotations_into(&mut s);
+                s.push('\n');
+                if let Some(ref help) = pos.help {
                     write_trimmed_n(&mut s, "    ", help);
                 }
                 s.push('\n');
             }
-            if let Some((ref name, optional, ref help)) = self.trail {
+            if let Some((ref pos, optional)) = self.trail {
                 s.push_str("  ");
-                if optional {
-                    s.push_str(&format!("[{}...]\n", name));
+                let signature = if optional || pos.default.is_some() {
+                    format!("[{}...]", pos.name)
                 } else {
-                    s.push_str(&format!("{} [{}...]\n", name, name));
+                    format!("{} [{}...]", pos.name, pos.name)
+                };
+                s.push_str(&style.name(&signature));
+                pos.write_annotations_into(&mut s);
+                s.push('\n');
+                if let Some(ref help) = pos.help {
+                    write_trimmed_n(&mut s, "    ", help);
                 }
-                if let &Some(ref help) = help {
+                s.push('\n');
+            }
+            if let Some(ref pos) = self.passthrough {
+                s.push_str(&format!("  {}\n", style.name(&pos.passthrough_signature())));
+                if let Some(ref help) = pos.help {
                     write_trimmed_n(&mut s, "    ", help);
                 }
                 s.push('\n');
@@ -186,9 +474,16 @@ impl<'def> Help<'def> {
         
         if has_subcommands {
             s.push('\n');
-            s.push_str("Subcommands:\n");
+            s.push_str(&style.header("Subcommands:"));
+            s.push('\n');
             for &(ref name, ref help) in self.subcommands.iter() {
-                s.push_str(&format!("  {}\n", name));
+                s.push_str(&format!("  {}", style.name(name)));
+                for &(ref alias, ref aliased) in self.aliases.iter() {
+                    if aliased == name {
+                        s.push_str(&format!(", {}", style.name(alias)));
+                    }
+                }
+                s.push('\n');
                 if let &Some(ref help) = help {
                     write_trimmed_n(&mut s, "    ", help);
                 }
@@ -200,25 +495,14 @@ impl<'def> Help<'def> {
             if ! (has_positional || has_subcommands) {
                 s.push('\n');
             }
-            s.push_str("Optional arguments:\n");
-            for &(ref name, ref short, kind, ref help) in self.options.iter() {
+            s.push_str(&style.header("Optional arguments:"));
+            s.push('\n');
+            for opt in self.options.iter() {
                 s.push_str("  ");
-                s.push_str("--");
-                s.push_str(name.as_ref());
-                if let &Some(ref short) = short {
-                    s.push_str(", ");
-                    s.push('-');
-                    s.push_str(short.as_ref());
-                }
-                match kind {
-                    HelpOptKind::OptArg => {
-                        s.push(' ');
-                        s.push_str(&name.as_ref().to_uppercase());
-                    }
-                    _ => {}
-                }
+                s.push_str(&style.name(&opt.signature()));
+                opt.write_annotations_into(&mut s);
                 s.push('\n');
-                if let &Some(ref help) = help {
+                if let Some(ref help) = opt.help {
                     write_trimmed_n(&mut s, "      ", help);
                     s.push('\n');
                 }
@@ -228,10 +512,164 @@ impl<'def> Help<'def> {
         s
     }
     
-    /// Prints a help message for this program, using the given program
-    /// description. The description may be left blank.
+    /// Prints a help message for this program to stdout, using the given 
+    /// program description. The description may be left blank.
     pub fn print_help(&self, description: &str) {
-        print!("{}", self.help_message(description));
+        self.write_help_to(&mut Sink::stdout(), description);
+    }
+    
+    /// Writes a help message for this program to the given sink, in its 
+    /// style.
+    pub fn write_help_to(&self, sink: &mut Sink, description: &str) {
+        let message = self.styled_help_message(description, sink.style);
+        sink.write_str(&message);
+    }
+}
+
+impl<'def> PosHelp<'def> {
+    fn new<'tar>(def: &ArgDef<'def, 'tar>, env: Option<Cow<'def, str>>, 
+            definitions: &[ArgDef<'def, 'tar>]) -> PosHelp<'def> {
+        PosHelp { 
+            name: def.name.clone(), 
+            help: def.help_desc.clone(), 
+            env,
+            default: def.default.clone(),
+            choices: def.choices.clone(),
+            requires: relation_names(&def.requires, definitions),
+            conflicts: relation_names(&def.conflicts, definitions),
+        }
+    }
+    
+    fn to_static(&self) -> PosHelp<'static> {
+        PosHelp {
+            name: own(&self.name),
+            help: self.help.as_ref().map(own),
+            env: self.env.as_ref().map(own),
+            default: self.default.as_ref().map(own),
+            choices: self.choices.iter().map(own).collect(),
+            requires: self.requires.iter().map(own).collect(),
+            conflicts: self.conflicts.iter().map(own).collect(),
+        }
+    }
+    
+    /// Returns how a passthrough argument is written, like `[-- ARGS...]`.
+    pub fn passthrough_signature(&self) -> String {
+        format!("[-- {}...]", self.name.to_uppercase())
+    }
+    
+    /// Writes the notes shown after the name, like `[env: NAME]`.
+    pub fn write_annotations_into(&self, s: &mut String) {
+        if ! self.choices.is_empty() {
+            s.push_str(&format!(" [possible values: {}]", self.choices.join(", ")));
+        }
+        write_relations_into(s, &self.requires, &self.conflicts);
+        write_annotations_into(s, &self.env, &self.default);
+    }
+}
+
+impl<'def> OptHelp<'def> {
+    fn to_static(&self) -> OptHelp<'static> {
+        OptHelp {
+            name: own(&self.name),
+            short: self.short.as_ref().map(own),
+            kind: self.kind,
+            metavar: self.metavar.as_ref().map(own),
+            help: self.help.as_ref().map(own),
+            env: self.env.as_ref().map(own),
+            default: self.default.as_ref().map(own),
+            choices: self.choices.iter().map(own).collect(),
+            required: self.required,
+            global: self.global,
+            negatable: self.negatable,
+            requires: self.requires.iter().map(own).collect(),
+            conflicts: self.conflicts.iter().map(own).collect(),
+        }
+    }
+    
+    /// Returns how the value of the option is written, like `OUTPUT`, or 
+    /// `INCLUDE...` for a repeated option.
+    pub fn value_signature(&self) -> Option<String> {
+        self.metavar.as_ref().map(|metavar| match self.kind {
+            HelpOptKind::Repeated => format!("{}...", metavar),
+            _ => metavar.to_string(),
+        })
+    }
+    
+    /// Returns how the option is written, like `--output, -o OUTPUT`.
+    pub fn signature(&self) -> String {
+        let mut s = if self.negatable {
+            format!("--[no-]{}", self.name)
+        } else {
+            format!("--{}", self.name)
+        };
+        if let Some(ref short) = self.short {
+            s.push_str(&format!(", -{}", short));
+        }
+        if let Some(value) = self.value_signature() {
+            s.push(' ');
+            s.push_str(&value);
+        }
+        s
+    }
+    
+    /// Writes the notes shown after the option, like `[env: NAME]`.
+    pub fn write_annotations_into(&self, s: &mut String) {
+        if self.required {
+            s.push_str(" [required]");
+        }
+        write_relations_into(s, &self.requires, &self.conflicts);
+        write_annotations_into(s, &self.env, &self.default);
+    }
+}
+
+/// Returns the name of the value an option takes, like `FORMAT`, or 
+/// `json|yaml` when it has choices.
+fn value_name<'def, 'tar>(def: &ArgDef<'def, 'tar>) -> Cow<'def, str> {
+    if def.choices.is_empty() {
+        def.name.to_uppercase().into()
+    } else {
+        def.choices.join("|").into()
+    }
+}
+
+/// Returns how the named arguments are written in the usage, so that options
+/// are shown like `--cert`, and positional arguments and groups by name.
+fn relation_names<'def, 'tar>(names: &[Cow<'def, str>], definitions: &[ArgDef<'def, 'tar>]) 
+        -> Vec<Cow<'def, str>> {
+    names.iter().map(|name| {
+        let def = definitions.iter().find(|def| def.name == *name);
+        match def.map(|def| &def.kind) {
+            Some(&ArgDefKind::Flag { .. }) | Some(&ArgDefKind::Tristate { .. }) | 
+            Some(&ArgDefKind::Count { .. }) | Some(&ArgDefKind::OptArg { .. }) | 
+            Some(&ArgDefKind::Repeated { .. }) | Some(&ArgDefKind::Interrupt { .. }) => {
+                Cow::Owned(format!("--{}", name))
+            }
+            _ => name.clone(),
+        }
+    }).collect()
+}
+
+fn own<'def>(text: &Cow<'def, str>) -> Cow<'static, str> {
+    Cow::Owned(text.to_string())
+}
+
+fn write_relations_into<'def>(s: &mut String, requires: &[Cow<'def, str>], 
+        conflicts: &[Cow<'def, str>]) {
+    if ! requires.is_empty() {
+        s.push_str(&format!(" [requires: {}]", requires.join(", ")));
+    }
+    if ! conflicts.is_empty() {
+        s.push_str(&format!(" [conflicts with: {}]", conflicts.join(", ")));
+    }
+}
+
+fn write_annotations_into<'def>(s: &mut String, env: &Option<Cow<'def, str>>, 
+        default: &Option<Cow<'def, str>>) {
+    if let Some(ref env) = *env {
+        s.push_str(&format!(" [env: {}]", env));
+    }
+    if let Some(ref default) = *default {
+        s.push_str(&format!(" [default: {}]", default));
     }
 }
 
@@ -244,6 +682,8 @@ pub enum HelpOptKind {
     Count,
     /// An option with a value. `./bin --eat-cake yes`
     OptArg,
+    /// An option that can be repeated. `./bin --eat cake --eat pie`
+    Repeated,
     /// An interrupt. `./bin --help`
     Interrupt,
 }
@@ -0,0 +1,226 @@
+use std::env;
+use help::{Help, trim_and_strip_lines};
+use style::{self, Sink, Stream, Style};
+
+/// The width used when it is neither given, nor read from the terminal or
+/// `COLUMNS`.
+const DEFAULT_WIDTH: usize = 80;
+
+/// The widest the first column gets. Longer entries start their help on the
+/// next line.
+const MAX_COLUMN: usize = 30;
+
+/// How `Help::formatted_help` lays out a help message.
+#[derive(Debug, Clone)]
+pub struct HelpLayout {
+    /// The width to wrap at. When not set, the width of the terminal that
+    /// stdout writes to is used, then the `COLUMNS` environment variable, or
+    /// 80 if neither is known.
+    pub width: Option<usize>,
+    /// Whether the blank lines between entries are left out.
+    pub compact: bool,
+}
+
+impl HelpLayout {
+    /// Creates the default layout.
+    pub fn new() -> HelpLayout {
+        HelpLayout {
+            width: None,
+            compact: false,
+        }
+    }
+    
+    /// Wraps at the given width instead of the width of the terminal.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+    
+    /// Leaves out the blank lines between entries.
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
+    }
+    
+    /// Returns the width to wrap at.
+    pub fn line_width(&self) -> usize {
+        self.width
+            .or_else(|| style::terminal_width(Stream::Stdout))
+            .or_else(|| {
+                env::var("COLUMNS").ok()
+                    .and_then(|columns| columns.trim().parse().ok())
+            })
+            .unwrap_or(DEFAULT_WIDTH)
+    }
+}
+
+impl Default for HelpLayout {
+    fn default() -> HelpLayout {
+        HelpLayout::new()
+    }
+}
+
+/// Splits a text into lines of at most `width` characters, breaking at
+/// whitespace. Blank lines in the text are kept as paragraph breaks, and
+/// words longer than the width are left whole.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = ::std::cmp::max(width, 1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for source in trim_and_strip_lines(text) {
+        if source.is_empty() {
+            if ! line.is_empty() {
+                lines.push(line);
+                line = String::new();
+            }
+            lines.push(String::new());
+            continue;
+        }
+        for word in source.split_whitespace() {
+            let len = line.chars().count();
+            if len > 0 && len + 1 + word.chars().count() > width {
+                lines.push(line);
+                line = String::new();
+            }
+            if ! line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+    }
+    if ! line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// An entry of a section, like an option and its help.
+struct Entry {
+    signature: String,
+    help: String,
+}
+
+impl<'def> Help<'def> {
+    /// Generates a help message for this program like `help_message`, but
+    /// with the signatures and their help in two aligned columns, wrapped to
+    /// the width of the layout.
+    pub fn formatted_help(&self, description: &str, layout: &HelpLayout) -> String {
+        self.styled_formatted_help(description, layout, Style::plain())
+    }
+    
+    /// Like `formatted_help`, with bold section headers and colored names in
+    /// the given style.
+    pub fn styled_formatted_help(&self, description: &str, layout: &HelpLayout, 
+            style: Style) -> String {
+        let width = layout.line_width();
+        let mut s = format!("{}\n", style.header("Usage:"));
+        let mut usage = String::new();
+        self.write_usage_into(&mut usage);
+        for line in wrap(&usage, width.saturating_sub(2)) {
+            s.push_str(&format!("  {}\n", line));
+        }
+        
+        if ! description.trim().is_empty() {
+            s.push_str(&format!("\n{}\n", style.header("Description:")));
+            for line in wrap(description, width.saturating_sub(2)) {
+                if ! line.is_empty() {
+                    s.push_str("  ");
+                    s.push_str(&line);
+                }
+                s.push('\n');
+            }
+        }
+        
+        let positional: Vec<_> = self.positional_signatures().into_iter()
+            .map(|(pos, signature)| {
+                let mut help = pos.help.as_ref().map(|h| h.to_string()).unwrap_or_default();
+                pos.write_annotations_into(&mut help);
+                Entry { signature, help }
+            })
+            .collect();
+        let subcommands: Vec<_> = self.subcommands.iter()
+            .map(|&(ref name, ref help)| {
+                let mut signature = name.to_string();
+                for &(ref alias, ref aliased) in self.aliases.iter() {
+                    if aliased == name {
+                        signature.push_str(&format!(", {}", alias));
+                    }
+                }
+                let help = help.as_ref().map(|h| h.to_string()).unwrap_or_default();
+                Entry { signature, help }
+            })
+            .collect();
+        let options: Vec<_> = self.options.iter()
+            .map(|opt| {
+                let mut help = opt.help.as_ref().map(|h| h.to_string()).unwrap_or_default();
+                opt.write_annotations_into(&mut help);
+                Entry { signature: opt.signature(), help }
+            })
+            .collect();
+        
+        // Every section shares the same column, so that they line up.
+        let column = positional.iter().chain(subcommands.iter()).chain(options.iter())
+            .map(|entry| entry.signature.chars().count())
+            .filter(|&len| len <= MAX_COLUMN)
+            .max()
+            .unwrap_or(0);
+        let sections = [
+            ("Positional arguments", &positional),
+            ("Subcommands", &subcommands),
+            ("Optional arguments", &options),
+        ];
+        for &(title, entries) in sections.iter() {
+            if entries.is_empty() {
+                continue;
+            }
+            s.push_str(&format!("\n{}\n", style.header(&format!("{}:", title))));
+            for (i, entry) in entries.iter().enumerate() {
+                if i > 0 && ! layout.compact {
+                    s.push('\n');
+                }
+                write_entry(&mut s, entry, column, width, style);
+            }
+        }
+        s
+    }
+    
+    /// Prints a help message for this program to stdout, laid out like in
+    /// `formatted_help`.
+    pub fn print_formatted_help(&self, description: &str, layout: &HelpLayout) {
+        self.write_formatted_help_to(&mut Sink::stdout(), description, layout);
+    }
+    
+    /// Writes a help message for this program to the given sink, in its
+    /// style, laid out like in `formatted_help`.
+    pub fn write_formatted_help_to(&self, sink: &mut Sink, description: &str, 
+            layout: &HelpLayout) {
+        let message = self.styled_formatted_help(description, layout, sink.style);
+        sink.write_str(&message);
+    }
+}
+
+/// Writes an entry with its help in the column starting after `column`
+/// characters of signature, or on the next lines if the signature is wider.
+fn write_entry(s: &mut String, entry: &Entry, column: usize, width: usize, style: Style) {
+    // Two spaces before the signature, and two between the columns.
+    let indent = column + 4;
+    let help = wrap(&entry.help, width.saturating_sub(indent));
+    let len = entry.signature.chars().count();
+    s.push_str("  ");
+    s.push_str(&style.name(&entry.signature));
+    let mut lines = help.iter();
+    if len <= column {
+        if let Some(first) = lines.next() {
+            s.push_str(&" ".repeat(column - len + 2));
+            s.push_str(first);
+        }
+    }
+    s.push('\n');
+    for line in lines {
+        if ! line.is_empty() {
+            s.push_str(&" ".repeat(indent));
+            s.push_str(line);
+        }
+        s.push('\n');
+    }
+}
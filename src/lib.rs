@@ -17,12 +17,34 @@ The lifetime of target pointers used when defining arguments.
 
 extern crate std_unicode;
 
+#[macro_use]
+mod declare;
+
 mod argdef;
+mod complete;
+mod config;
+mod docs;
 mod help;
+mod layout;
+mod matches;
+mod os;
 mod parse;
-
-pub use argdef::{ArgDef, ArgDefKind, SingleTarget, CollectionTarget, OptionTarget};
-pub use parse::{parse, parse_plain, ParseError};
+mod parser;
+mod response;
+mod settings;
+mod style;
+
+pub use argdef::{ArgDef, ArgDefKind, SingleTarget, CollectionTarget, OptionTarget, OsTarget, Prepared};
+pub use parse::{parse, parse_plain, parse_with, parse_plain_with, parse_str, parse_str_with,
+    parse_os, parse_os_with, parse_plain_os, parse_plain_os_with, ParseError, ParseFailure, ErrorKind};
+pub use parse::{parse_matches, parse_matches_with};
+pub use matches::{Matches, ValueSource};
+pub use parser::{Parser, DefineFn};
+pub use settings::ParseSettings;
+pub use layout::HelpLayout;
+pub use style::{Style, Sink, Stream};
+pub use config::{ConfigFile, ConfigEntry, ConfigError};
+pub use complete::Shell;
 
 use std::borrow::{Cow};
 
@@ -30,7 +52,8 @@ use std::borrow::{Cow};
 DESIGN: Do I wait with assigning values until all arguments have been 'satisfied'?
 Or do I just start parsing/assigning as soon as possible so that bad arguments
 are caught faster?
-For now it'll be 2, since that seems simpler
+It used to be 2, but that leaves the targets half-assigned when a parse
+fails, so it's now 1.
 
 # option 1
 read through the arguments and assign each to a matching option
@@ -45,8 +68,6 @@ return success
 Tasks
 
 Optional
-- Implement a validate->assign->modify procedure in parse
-- Make a passthrough argument (cargo run -- --help)
 
 Done
 - Usage generator (printer)
@@ -54,6 +75,9 @@ Done
 - Simple subcommand abstraction
 - Validate 'short' identifiers
 - Change default parse function to write usage (no parse_subcommand)
+- Implement a validate->assign->modify procedure in parse
+- Shell completion generator (bash, zsh, fish)
+- Make a passthrough argument (cargo run -- --help)
 
 Abandoned
 - Make a default handler function for parse results.
@@ -66,14 +90,82 @@ pub fn help_arg<'def, 'tar, D>(description: D)
   where D: Into<Cow<'static, str>>
 {
     let description = description.into();
-    ArgDef::interrupt("help", move |help| {
-        help.print_help(description.as_ref());
+    ArgDef::interrupt("help", move |help, sink| {
+        help.write_help_to(sink, description.as_ref());
     }).help("Print this message and abort.")
 }
 
+/// Creates a help interrupt for `--help` like `help_arg`, which lays out the
+/// help message like `Help::formatted_help`.
+pub fn formatted_help_arg<'def, 'tar, D>(description: D, layout: HelpLayout)
+        -> ArgDef<'def, 'tar> 
+  where D: Into<Cow<'static, str>>
+{
+    let description = description.into();
+    ArgDef::interrupt("help", move |help, sink| {
+        help.write_formatted_help_to(sink, description.as_ref(), &layout);
+    }).help("Print this message and abort.")
+}
+
+/// Creates a default interrupt for `--completions SHELL`, which prints a
+/// completion script for `bash`, `zsh` or `fish`. Other shells fail the parse
+/// like any invalid choice.
+pub fn completions_arg<'def, 'tar>() -> ArgDef<'def, 'tar> {
+    ArgDef::interrupt_arg("completions", "SHELL", |help, shell, sink| {
+        // INVARIANT: The shell is one of the choices
+        let shell = shell.parse::<Shell>().unwrap();
+        sink.write_str(&help.completions(shell));
+    })
+    .choices(&["bash", "zsh", "fish"])
+    .help("Print a completion script for the given shell and abort.")
+}
+
 /// Creates a default version interrupt for `--version`.
 pub fn version_arg<'def, 'tar>() -> ArgDef<'def, 'tar> {
-    ArgDef::interrupt("version", |_| {
-        println!("{}", option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0"));
+    ArgDef::interrupt("version", |_, sink| {
+        let version = option_env!("CARGO_PKG_VERSION").unwrap_or("0.0.0");
+        sink.write_str(&format!("{}\n", version));
     }).help("Print version string and abort.")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use style::Captured;
+    
+    /// Parses the given arguments, returning what was written to the output
+    /// sink and to the error sink.
+    fn interrupt(args: &[&str], definitions: Vec<ArgDef<'static, 'static>>, style: Style) 
+            -> (String, String) {
+        let (output, output_text) = Captured::sink(style);
+        let (errors, error_text) = Captured::sink(style);
+        let settings = ParseSettings::new().output_sink(output).error_sink(errors);
+        match parse_with("tool", args, definitions, &settings) {
+            Err(ParseError::Interrupted(_)) => {}
+            other => panic!("Expected an interrupt, got {:?}", other),
+        }
+        (output_text.text(), error_text.text())
+    }
+    
+    #[test]
+    fn help_is_written_to_the_output_sink() {
+        let (output, errors) = interrupt(&["--help"], vec![help_arg("Does things.")], Style::plain());
+        assert_eq!("Usage:\n  tool [ --help ]\n\nDescription:\n  Does things.\n\n\
+            Optional arguments:\n  --help\n      Print this message and abort.\n\n", output);
+        assert_eq!("", errors);
+    }
+    
+    #[test]
+    fn styled_help_is_written_in_color() {
+        let (output, _) = interrupt(&["--help"], vec![help_arg("")], Style::styled());
+        assert!(output.starts_with("\x1b[1mUsage:\x1b[0m\n"));
+        assert!(output.contains("\x1b[36m--help\x1b[0m"));
+    }
+    
+    #[test]
+    fn version_is_written_to_the_output_sink() {
+        let (output, errors) = interrupt(&["--version"], vec![version_arg()], Style::styled());
+        assert_eq!(format!("{}\n", env!("CARGO_PKG_VERSION")), output);
+        assert_eq!("", errors);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Where the value of an argument came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValueSource {
+    /// The value was given as an argument.
+    CommandLine,
+    /// The value was read from the named environment variable.
+    Environment(String),
+    /// The value was read from a config file. (path, line)
+    ConfigFile(String, usize),
+    /// The default value of the argument was used.
+    Default,
+}
+
+/// The values read by a parse, by argument name.
+///
+/// This is what `parse_matches` gives instead of writing to targets, so that
+/// definitions can be made at runtime without a variable for each one.
+///
+/// # Example
+/// ```
+/// # use playground::*;
+/// let matches = parse_matches("tool", &["-vv", "--port", "80"], vec![
+///     ArgDef::unbound_count("verbose").short("v"),
+///     ArgDef::unbound_option("port"),
+/// ]).unwrap();
+/// assert_eq!(2, matches.occurrences("verbose"));
+/// assert_eq!(Ok(Some(80)), matches.get::<u16>("port"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Matches {
+    // name-to-values, in the order they were given
+    values: HashMap<String, Vec<String>>,
+    // name-to-count, for flags and counts
+    occurrences: HashMap<String, usize>,
+    // Where the values that weren't given as arguments came from.
+    sources: HashMap<String, ValueSource>,
+    subcommand: Option<(String, Box<Matches>)>,
+}
+
+impl Matches {
+    /// Returns the last value of the named argument, parsed as `T`.
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, T::Err> {
+        match self.value_of(name) {
+            Some(value) => value.parse().map(Some),
+            None => Ok(None),
+        }
+    }
+    
+    /// Returns every value of the named argument, parsed as `T`.
+    pub fn get_all<T: FromStr>(&self, name: &str) -> Result<Vec<T>, T::Err> {
+        self.values_of(name).iter().map(|value| value.parse()).collect()
+    }
+    
+    /// Returns the last value of the named argument.
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        self.values_of(name).last().map(|value| value.as_str())
+    }
+    
+    /// Returns every value of the named argument, in the order they were
+    /// given. Positional arguments and options have at most one.
+    pub fn values_of(&self, name: &str) -> &[String] {
+        self.values.get(name).map(|values| values.as_slice()).unwrap_or(&[])
+    }
+    
+    /// Returns how many times the named flag or count was given. Flags are
+    /// given at most once.
+    pub fn occurrences(&self, name: &str) -> usize {
+        self.occurrences.get(name).cloned().unwrap_or(0)
+    }
+    
+    /// Returns whether the named argument has a value or was given at all,
+    /// from any source.
+    pub fn is_present(&self, name: &str) -> bool {
+        self.values.contains_key(name) || self.occurrences(name) > 0
+    }
+    
+    /// Returns where the value of the named argument came from, if it has one.
+    pub fn source(&self, name: &str) -> Option<&ValueSource> {
+        if ! self.is_present(name) {
+            return None;
+        }
+        Some(self.sources.get(name).unwrap_or(&ValueSource::CommandLine))
+    }
+    
+    /// Returns the name and the matches of the subcommand that was run, if
+    /// any.
+    ///
+    /// The matches of a subcommand are those of the first `parse_matches`
+    /// run by its handler, and are empty if it runs none.
+    pub fn subcommand(&self) -> Option<(&str, &Matches)> {
+        self.subcommand.as_ref().map(|&(ref name, ref matches)| (name.as_str(), &**matches))
+    }
+}
+
+/// Creates the matches of a parse.
+pub fn assemble(values: HashMap<String, Vec<String>>, occurrences: HashMap<String, usize>,
+        sources: HashMap<String, ValueSource>, subcommand: Option<(String, Matches)>) -> Matches {
+    Matches {
+        values,
+        occurrences,
+        sources,
+        subcommand: subcommand.map(|(name, matches)| (name, Box::new(matches))),
+    }
+}
@@ -0,0 +1,58 @@
+use std::ffi::{OsStr, OsString};
+#[cfg(unix)]
+use std::str;
+
+/// Turns arguments into the text that the parse reads, along with the
+/// original of every argument that isn't valid UTF-8. The text of those is
+/// converted lossily.
+pub fn split_originals<A: AsRef<OsStr>>(args: &[A]) -> (Vec<String>, Vec<Option<OsString>>) {
+    args.iter().map(|arg| {
+        let arg = arg.as_ref();
+        match arg.to_str() {
+            Some(text) => (text.to_string(), None),
+            None => (arg.to_string_lossy().into_owned(), Some(arg.to_os_string())),
+        }
+    }).unzip()
+}
+
+/// Returns the part of an argument that the bytes from `start` to `end` of
+/// its lossy conversion were read from.
+#[cfg(unix)]
+pub fn slice(arg: &OsStr, start: usize, end: usize) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    let bytes = arg.as_bytes();
+    let start = byte_offset(bytes, start);
+    let end = byte_offset(bytes, end);
+    OsStr::from_bytes(&bytes[start..end]).to_os_string()
+}
+
+/// Returns the part of an argument that the bytes from `start` to `end` of
+/// its lossy conversion were read from. Only the converted text is known on
+/// this platform.
+#[cfg(not(unix))]
+pub fn slice(arg: &OsStr, start: usize, end: usize) -> OsString {
+    OsString::from(&arg.to_string_lossy()[start..end])
+}
+
+/// Finds the byte of the given text that the given offset into its lossy
+/// conversion belongs to. Every invalid sequence is converted to a single
+/// replacement character.
+#[cfg(unix)]
+fn byte_offset(bytes: &[u8], offset: usize) -> usize {
+    const REPLACEMENT_LEN: usize = 3;
+    // The offsets reached so far in the conversion and in the bytes.
+    let (mut converted, mut read) = (0, 0);
+    loop {
+        let rest = &bytes[read..];
+        let (valid, invalid) = match str::from_utf8(rest) {
+            Ok(_) => return read + offset - converted,
+            Err(err) => (err.valid_up_to(), err.error_len().unwrap_or(rest.len() - err.valid_up_to())),
+        };
+        if offset < converted + valid + REPLACEMENT_LEN {
+            // Offsets within a replacement character are not char boundaries.
+            return read + ::std::cmp::min(offset - converted, valid);
+        }
+        converted += valid + REPLACEMENT_LEN;
+        read += valid + invalid;
+    }
+}
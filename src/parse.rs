@@ -1,48 +1,400 @@
-use argdef::{SingleTarget, CollectionTarget, OptionTarget, ArgDef, ArgDefKind, SubCmd};
-use help::Help;
-use std::collections::{HashMap, HashSet, VecDeque};
+use argdef::{SingleTarget, CollectionTarget, OptionTarget, Prepared, ArgDef, ArgDefKind, SubCmd, InterruptFn};
+use help::{Help, OptHelp};
+use matches::{self, Matches, ValueSource};
+use settings::ParseSettings;
+use style::Sink;
+use config;
+use response;
+use os;
+use std::collections::{HashMap, HashSet};
 use std::borrow::{Cow, Borrow};
+use std::cell::RefCell;
 use std::rc::Rc;
+use std::fmt;
+use std::ffi::{OsStr, OsString};
 
-/// References to the targets of non-positional arguments.
+/// References to the targets of non-positional arguments. Unbound arguments
+/// have no target.
 //#[derive(Debug)]
 pub enum TargetRef<'def, 'tar> {
-    Flag(&'tar mut bool),
-    Count(&'tar mut usize),
-    OptArg(&'tar mut OptionTarget),
-    Interrupt(Box<FnMut(Rc<Help<'def>>)>),
+    Flag {
+        target: Option<&'tar mut bool>,
+        negatable: bool,
+    },
+    Tristate(Option<&'tar mut Option<bool>>),
+    Count(Option<&'tar mut usize>),
+    OptArg(Option<Box<OptionTarget + 'tar>>),
+    Repeated {
+        target: Option<Box<CollectionTarget + 'tar>>,
+        delimiter: Option<char>,
+    },
+    Interrupt {
+        callback: InterruptFn<'def>,
+        takes_value: bool,
+    },
 }
 
 /// Sorted argument definitions. Updated mutably during the parse.
 //#[derive(Debug)]
+#[derive(Default)]
 pub struct ParseState<'def, 'tar> {
-    positional: VecDeque<(Cow<'def, str>, &'tar mut SingleTarget)>,
-    // (satisfied, target)
-    trail: Option<(Cow<'def, str>, bool, &'tar mut CollectionTarget)>,
+    positional: Vec<(Cow<'def, str>, Option<Box<SingleTarget + 'tar>>)>,
+    // (optional, target)
+    trail: Option<(Cow<'def, str>, bool, Option<Box<CollectionTarget + 'tar>>)>,
+    passthrough: Option<(Cow<'def, str>, Option<&'tar mut Vec<String>>)>,
     subcommands: HashMap<Cow<'def, str>, SubCmd<'def>>,
+    // name-to-children, for subcommands given by their definitions
+    commands: HashMap<Cow<'def, str>, Vec<ArgDef<'def, 'tar>>>,
+    // alias-to-name, for subcommands
+    aliases: HashMap<Cow<'def, str>, Cow<'def, str>>,
     options: HashMap<Cow<'def, str>, TargetRef<'def, 'tar>>,
     short_map: HashMap<Cow<'def, str>, Cow<'def, str>>,
+    // name-to-variable
+    env: HashMap<Cow<'def, str>, Cow<'def, str>>,
+    // name-to-value
+    defaults: HashMap<Cow<'def, str>, Cow<'def, str>>,
+    // The options that must be given
+    required: Vec<Cow<'def, str>>,
+    // name-to-(choices, ignore case)
+    choices: HashMap<Cow<'def, str>, (Vec<Cow<'def, str>>, bool)>,
+    groups: Vec<Group<'def>>,
+    // (name, requires, conflicts)
+    relations: Vec<(Cow<'def, str>, Vec<Cow<'def, str>>, Vec<Cow<'def, str>>)>,
+    // The options that are handed down to the subcommands
+    globals: Vec<Cow<'def, str>>,
+    // The options and subcommands that are never suggested
+    hidden: HashSet<Cow<'def, str>>,
+}
+
+/// A group of arguments.
+#[derive(Debug)]
+struct Group<'def> {
+    name: Cow<'def, str>,
+    members: Vec<Cow<'def, str>>,
+    exclusive: bool,
+    required: bool,
+}
+
+/// Raw values read from the arguments. These are only parsed and written to
+/// the targets once every argument has been read.
+#[derive(Debug, Default)]
+struct Staged<'def> {
+    positional: Vec<RawValue>,
+    trail: Vec<RawValue>,
+    passthrough: Vec<String>,
+    // The value of each flag given, false when turned off with `--no-<name>`.
+    flags: HashMap<Cow<'def, str>, bool>,
+    counts: HashMap<Cow<'def, str>, usize>,
+    values: HashMap<Cow<'def, str>, RawValue>,
+    lists: HashMap<Cow<'def, str>, Vec<RawValue>>,
+    // Where the values that weren't given as arguments came from.
+    sources: HashMap<Cow<'def, str>, ValueSource>,
+    // The values parsed by the targets, once every value has been checked.
+    prepared: Vec<(Slot<'def>, Prepared)>,
+}
+
+/// The target that a prepared value is written to.
+#[derive(Debug)]
+enum Slot<'def> {
+    Positional(usize),
+    Trail,
+    Option(Cow<'def, str>),
+}
+
+/// A staged value, along with the index of the argument it was read from.
+#[derive(Debug, Clone)]
+struct RawValue {
+    text: String,
+    // None when not read from the arguments.
+    index: Option<usize>,
+    // The number of bytes of the argument after the text, since values given
+    // inline end the argument, and only those split at a delimiter don't.
+    after: usize,
+}
+
+impl RawValue {
+    fn given(text: &str, index: usize) -> RawValue {
+        RawValue { text: text.to_string(), index: Some(index), after: 0 }
+    }
+    
+    fn fallback(text: String) -> RawValue {
+        RawValue { text, index: None, after: 0 }
+    }
+    
+    /// Returns the value as it was passed to `parse_os`, if it was read from
+    /// an argument that isn't valid UTF-8.
+    fn original(&self, originals: &[Option<OsString>]) -> Option<OsString> {
+        let arg = match self.index.and_then(|index| originals.get(index)) {
+            Some(&Some(ref arg)) => arg,
+            _ => return None,
+        };
+        let end = arg.to_string_lossy().len() - self.after;
+        Some(os::slice(arg, end - self.text.len(), end))
+    }
+}
+
+impl<'def> Staged<'def> {
+    /// Adds the source of the named value to a message about it.
+    fn describe(&self, name: &str, msg: String) -> String {
+        match self.sources.get(name) {
+            Some(source) => source.describe(msg),
+            None => msg,
+        }
+    }
+}
+
+impl ValueSource {
+    /// Adds this source to a message about a value.
+    fn describe(&self, msg: String) -> String {
+        match *self {
+            ValueSource::CommandLine => msg,
+            ValueSource::Environment(ref var) => {
+                format!("{} (from environment variable '{}')", msg, var)
+            }
+            ValueSource::ConfigFile(ref path, line) => format!("{} (at {}:{})", msg, path, line),
+            ValueSource::Default => format!("{} (from the default value)", msg),
+        }
+    }
+}
+
+/// Splits a value of a repeated option at its delimiter, if it has one.
+fn split_values(value: RawValue, delimiter: Option<char>) -> Vec<RawValue> {
+    let delimiter = match delimiter {
+        Some(delimiter) => delimiter,
+        None => return vec![value],
+    };
+    let mut start = 0;
+    value.text.split(delimiter)
+        .map(|text| {
+            let end = start + text.len();
+            start = end + delimiter.len_utf8();
+            let after = value.after + value.text.len() - end;
+            RawValue { text: text.to_string(), index: value.index, after }
+        })
+        .collect()
+}
+
+/// Has a collection target parse the given values, as they were passed to 
+/// `parse_os` if any of them was read from an argument that isn't valid UTF-8.
+fn prepare_all(target: &CollectionTarget, values: &[RawValue], originals: &[Option<OsString>]) 
+        -> Result<Prepared, (usize, String)> {
+    let found: Vec<_> = values.iter().map(|value| value.original(originals)).collect();
+    if found.iter().all(|original| original.is_none()) {
+        let texts: Vec<_> = values.iter().map(|value| value.text.as_str()).collect();
+        return target.prepare(&texts);
+    }
+    let raw: Vec<OsString> = found.into_iter().zip(values.iter())
+        .map(|(original, value)| original.unwrap_or_else(|| OsString::from(&value.text)))
+        .collect();
+    let raw: Vec<&OsStr> = raw.iter().map(|value| value.as_os_str()).collect();
+    target.prepare_raw(&raw)
+}
+
+/// Takes the value of an option, either given inline or as the next argument.
+fn take_value<'arg, I>(inline: Option<&str>, index: usize, args: &mut I) -> Option<RawValue>
+  where I: Iterator<Item=(usize, &'arg str)>
+{
+    match inline {
+        Some(text) => Some(RawValue::given(text, index)),
+        None => args.next().map(|(index, text)| RawValue::given(text, index)),
+    }
+}
+
+/// Returns the number of single character edits that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substituted = diagonal + if ca == cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Picks the candidates closest to what was given, at most three of them.
+/// The candidates are (name to compare, name to suggest).
+fn suggest<'a, I>(given: &str, candidates: I) -> Vec<String> 
+  where I: IntoIterator<Item=(&'a str, String)> 
+{
+    let len = given.chars().count();
+    let limit = ::std::cmp::max(2, len / 3);
+    let mut close: Vec<_> = candidates.into_iter()
+        .map(|(name, suggestion)| (edit_distance(given, name), suggestion))
+        .filter(|&(distance, _)| distance <= limit && distance < len)
+        .collect();
+    close.sort();
+    close.dedup_by(|a, b| a.1 == b.1);
+    close.into_iter().take(3).map(|(_, suggestion)| suggestion).collect()
+}
+
+/// Returns the choice that a value matches, if any.
+fn find_choice<'a, 'def>(choices: &'a [Cow<'def, str>], ignore_case: bool, text: &str) 
+        -> Option<&'a Cow<'def, str>> {
+    choices.iter().find(|choice| {
+        if ignore_case {
+            choice.to_lowercase() == text.to_lowercase()
+        } else {
+            choice.as_ref() == text
+        }
+    })
+}
+
+/// Fails on a value that isn't one of the choices of its argument.
+fn invalid_choice<'def, T>(name: &str, display_name: String, value: &RawValue, 
+        choices: &[Cow<'def, str>], source: Option<&ValueSource>, help: Rc<Help<'def>>) 
+        -> Result<T, ParseError<'def>> {
+    let msg = format!("Invalid value '{}' for '{}'. Possible values: {}", 
+        value.text, display_name, choices.join(", "));
+    let msg = match source {
+        Some(source) => source.describe(msg),
+        None => msg,
+    };
+    ParseFailure::new(ErrorKind::InvalidChoice, msg, help)
+        .at(value.index).text(value.text.clone()).name(name).fail()
+}
+
+/// Reads a flag value from an environment variable.
+fn parse_env_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "" | "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
 }
 
 impl<'def, 'tar> ParseState<'def, 'tar> {
+    /// Returns whether any subcommand is defined.
+    fn has_subcommands(&self) -> bool {
+        ! (self.subcommands.is_empty() && self.commands.is_empty())
+    }
+    
+    /// Moves the global options out of these definitions, along with their
+    /// staged values, so that they can be handed to a subcommand. Their names
+    /// are kept, for the relations that refer to them.
+    fn split_globals(&mut self, staged: &mut Staged<'def>) -> (ParseState<'def, 'tar>, Staged<'def>) {
+        let mut globals = ParseState::default();
+        let mut moved = Staged::default();
+        for name in self.globals.iter() {
+            if let Some(target) = self.options.remove(name) {
+                globals.options.insert(name.clone(), target);
+            }
+            let shorts: Vec<_> = self.short_map.iter()
+                .filter(|&(_, long)| long == name)
+                .map(|(short, _)| short.clone())
+                .collect();
+            for short in shorts {
+                self.short_map.remove(&short);
+                globals.short_map.insert(short, name.clone());
+            }
+            if let Some(var) = self.env.remove(name) {
+                globals.env.insert(name.clone(), var);
+            }
+            if let Some(value) = self.defaults.remove(name) {
+                globals.defaults.insert(name.clone(), value);
+            }
+            if let Some(choices) = self.choices.remove(name) {
+                globals.choices.insert(name.clone(), choices);
+            }
+            if self.required.contains(name) {
+                self.required.retain(|n| n != name);
+                globals.required.push(name.clone());
+            }
+            if self.hidden.remove(name) {
+                globals.hidden.insert(name.clone());
+            }
+            
+            if let Some(value) = staged.flags.remove(name) {
+                moved.flags.insert(name.clone(), value);
+            }
+            if let Some(count) = staged.counts.remove(name) {
+                moved.counts.insert(name.clone(), count);
+            }
+            if let Some(value) = staged.values.remove(name) {
+                moved.values.insert(name.clone(), value);
+            }
+            if let Some(values) = staged.lists.remove(name) {
+                moved.lists.insert(name.clone(), values);
+            }
+            if let Some(source) = staged.sources.remove(name) {
+                moved.sources.insert(name.clone(), source);
+            }
+        }
+        globals.globals = self.globals.clone();
+        (globals, moved)
+    }
+    
+    /// Adds the global options of the enclosing command to these definitions,
+    /// along with the values staged for them so far.
+    fn merge_globals(&mut self, globals: ParseState<'def, 'tar>, staged: &mut Staged<'def>, 
+            moved: Staged<'def>) -> Result<(), ParseError<'def>> {
+        for (name, target) in globals.options {
+            if self.options.contains_key(&name) {
+                return ParseError::defs(format!("Option '{}' defined twice.", name));
+            }
+            self.options.insert(name, target);
+        }
+        for (short, name) in globals.short_map {
+            if self.short_map.contains_key(&short) {
+                return ParseError::defs(format!("Short name '{}' defined twice.", short));
+            }
+            self.short_map.insert(short, name);
+        }
+        self.env.extend(globals.env);
+        self.defaults.extend(globals.defaults);
+        self.choices.extend(globals.choices);
+        self.required.extend(globals.required);
+        self.globals.extend(globals.globals);
+        self.hidden.extend(globals.hidden);
+        
+        staged.flags.extend(moved.flags);
+        staged.counts.extend(moved.counts);
+        staged.values.extend(moved.values);
+        staged.lists.extend(moved.lists);
+        staged.sources.extend(moved.sources);
+        Ok(())
+    }
+    
+    /// Returns whether the named flag can be turned off with `--no-<name>`.
+    fn is_negatable(&self, name: &str) -> bool {
+        match self.options.get(name) {
+            Some(&TargetRef::Flag { negatable, .. }) => negatable,
+            Some(&TargetRef::Tristate(_)) => true,
+            _ => false,
+        }
+    }
+    
     /// Returns the internal object representing the given option name.
     fn get_interned_name(&self, option: &str) -> Cow<'def, str> {
         self.options.keys().find(|k| k.as_ref() == option).unwrap().clone()
     }
     
-    /// Attempts to find a target from the given option.
-    fn get_target<'a>(&'a mut self, option: &str, help: Rc<Help<'def>>)
+    /// Fails on an unknown option, suggesting the closest known ones.
+    fn unknown_option<T>(&self, option: &str, index: usize, help: Rc<Help<'def>>) 
+            -> Result<T, ParseError<'def>> {
+        let given = option.trim_left_matches('-');
+        let longs = self.options.keys()
+            .filter(|name| ! self.hidden.contains(*name))
+            .map(|name| (name.as_ref(), format!("--{}", name)));
+        let shorts = self.short_map.iter()
+            .filter(|&(_, name)| ! self.hidden.contains(name))
+            .map(|(short, _)| (short.as_ref(), format!("-{}", short)));
+        let suggestions = suggest(given, longs.chain(shorts));
+        ParseFailure::new(ErrorKind::UnknownOption, format!("Unknown option '{}'", option), help)
+            .at(Some(index)).text(option).suggest(suggestions).fail()
+    }
+    
+    /// Attempts to find the target of the option with the given long name.
+    ///
+    /// `option` is the argument as it was written, and is used for errors.
+    fn get_target<'a>(&'a mut self, key: &str, option: &str, index: usize, help: Rc<Help<'def>>)
             -> Result<(Cow<'def, str>, &'a mut TargetRef<'def, 'tar>), ParseError<'def>> {
-        let mut key = &option[2..];
-        if ! option.starts_with("--") {
-            if let Some(mapped_key) = self.short_map.get(&option[1..]) {
-                key = mapped_key.as_ref();
-            } else {
-                return ParseError::parse(format!("Unknown option: '{}'", option), help);
-            }
-        }
         if ! self.options.contains_key(key) {
-            return ParseError::parse(format!("Unknown option '{}'", option), help);
+            return self.unknown_option(option, index, help);
         }
         // INVARIANT: key is contained
         let name = self.get_interned_name(key);
@@ -50,42 +402,681 @@ impl<'def, 'tar> ParseState<'def, 'tar> {
         Ok((name, target))
     }
     
+    /// Reads an option argument (`--name`, `--name=value`, `-n` or a cluster
+    /// like `-vvx` / `-ofile.txt` / `-o=file.txt`), taking a value from `args`
+    /// if needed.
+    ///
+    /// `index` is the index of the argument, and `args` yields the following
+    /// arguments along with their indices.
+    fn read_option<'arg, I>(&mut self, arg: &str, index: usize, args: &mut I, 
+        staged: &mut Staged<'def>, settings: &ParseSettings, help: Rc<Help<'def>>) 
+        -> Result<Option<Cow<'def, str>>, ParseError<'def>>
+      where I: Iterator<Item=(usize, &'arg str)>
+    {
+        if arg.starts_with("--") {
+            let (option, inline) = match arg.find('=') {
+                Some(i) => (&arg[..i], Some(&arg[i + 1..])),
+                None => (arg, None),
+            };
+            return self.apply_option(&option[2..], option, index, inline, args, staged, settings, help);
+        }
+        
+        let shorts = &arg[1..];
+        if shorts.is_empty() {
+            return self.unknown_option(arg, index, help);
+        }
+        
+        // Short identifiers may be longer than one character, so an exact
+        // match takes precedence over reading the argument as a cluster.
+        if let Some(key) = self.short_map.get(shorts).cloned() {
+            return self.apply_option(&key, arg, index, None, args, staged, settings, help);
+        }
+        
+        for (i, c) in shorts.char_indices() {
+            let short = &shorts[i..i + c.len_utf8()];
+            let key = match self.short_map.get(short).cloned() {
+                Some(key) => key,
+                None if i == 0 => {
+                    return self.unknown_option(arg, index, help);
+                }
+                None => {
+                    // Maybe a long name was written with a single dash.
+                    let longs = self.options.keys()
+                        .filter(|name| ! self.hidden.contains(*name))
+                        .map(|name| (name.as_ref(), format!("--{}", name)));
+                    let suggestions = suggest(shorts, longs);
+                    let msg = format!("Unknown option '-{}' in '{}'", short, arg);
+                    return ParseFailure::new(ErrorKind::UnknownOption, msg, help)
+                        .at(Some(index)).text(format!("-{}", short)).suggest(suggestions).fail();
+                }
+            };
+            let takes_value = match self.options.get(key.as_ref()) {
+                Some(&TargetRef::OptArg(_)) | Some(&TargetRef::Repeated { .. }) => true,
+                Some(&TargetRef::Interrupt { takes_value, .. }) => takes_value,
+                _ => false,
+            };
+            let option = format!("-{}", short);
+            if takes_value {
+                // The rest of the cluster is the value, like in `-ofile.txt`
+                // or `-o=file.txt`.
+                let rest = &shorts[i + c.len_utf8()..];
+                let inline = if rest.starts_with('=') {
+                    Some(&rest[1..])
+                } else if rest.is_empty() {
+                    None
+                } else {
+                    Some(rest)
+                };
+                return self.apply_option(&key, &option, index, inline, args, staged, settings, help);
+            }
+            if let Some(interrupt) = self.apply_option(&key, &option, index, None, args, staged, settings, help.clone())? {
+                return Ok(Some(interrupt));
+            }
+        }
+        Ok(None)
+    }
     
-    fn read_option<'arg, I>(&mut self, option: &str, args: &mut I, 
-        given_values: &mut HashSet<Cow<'def, str>>, help: Rc<Help<'def>>) 
+    /// Applies a single option with the given long name, using the inline
+    /// value if one was given, and otherwise reading one from `args`.
+    fn apply_option<'arg, I>(&mut self, key: &str, option: &str, index: usize, 
+        inline: Option<&str>, args: &mut I, staged: &mut Staged<'def>, 
+        settings: &ParseSettings, help: Rc<Help<'def>>) 
         -> Result<Option<Cow<'def, str>>, ParseError<'def>>
-      where I: Iterator<Item=&'arg str>
+      where I: Iterator<Item=(usize, &'arg str)>
     {
         use self::TargetRef::*;
-        match self.get_target(option, help.clone())? {
-            (_, &mut Flag(ref mut flag)) => {
-                **flag = true;
-            }
-            (_, &mut Count(ref mut count)) => {
-                **count += 1;
+        // `--no-cache` turns off a negatable flag, unless an option is named
+        // like that.
+        if ! self.options.contains_key(key) && key.starts_with("no-") {
+            if self.is_negatable(&key[3..]) {
+                let name = self.get_interned_name(&key[3..]);
+                if inline.is_some() {
+                    return ParseFailure::new(ErrorKind::UnexpectedValue, 
+                        format!("Option '{}' does not take a value", option), help)
+                        .at(Some(index)).text(option).name(name).fail();
+                }
+                staged.flags.insert(name, false);
+                return Ok(None);
             }
-            (ref name, &mut OptArg(ref mut value)) => {
-                if given_values.contains(name) {
-                    return ParseError::parse(format!("Option '{}' given twice!", name), help);
+        }
+        // The choices of an interrupt are checked before its callback runs.
+        let choices = self.choices.get(key).cloned();
+        let missing_value = |name: &str, help| {
+            ParseFailure::new(ErrorKind::MissingValue, 
+                format!("Missing argument for option '{}'", option), help)
+                .at(Some(index)).text(option).name(name).fail()
+        };
+        match self.get_target(key, option, index, help.clone())? {
+            (ref name, &mut OptArg(_)) => {
+                if staged.values.contains_key(name) {
+                    return ParseFailure::new(ErrorKind::DuplicateOption, 
+                        format!("Option '{}' given twice!", name), help)
+                        .at(Some(index)).text(option).name(name).fail();
                 }
-                let arg = if let Some(arg) = args.next() {
-                    arg
-                } else {
-                    return ParseError::parse(format!("Missing argument for option '{}'", option), help);
+                let value = match take_value(inline, index, args) {
+                    Some(value) => value,
+                    None => return missing_value(name, help),
                 };
-                match value.parse(arg) {
-                    Ok(_) => {}
-                    Err(msg) => return ParseError::parse(msg, help),
+                staged.values.insert(name.clone(), value);
+                return Ok(None);
+            }
+            (ref name, &mut Repeated { delimiter, .. }) => {
+                let value = match take_value(inline, index, args) {
+                    Some(value) => value,
+                    None => return missing_value(name, help),
                 };
-                given_values.insert(name.clone());
+                staged.lists.entry(name.clone()).or_insert_with(Vec::new)
+                    .extend(split_values(value, delimiter));
+                return Ok(None);
             }
-            (ref name, &mut Interrupt(ref mut callback)) => {
-                callback(help);
+            (ref name, &mut Interrupt { ref mut callback, takes_value: true, .. }) => {
+                let value = match take_value(inline, index, args) {
+                    Some(value) => value,
+                    None => return missing_value(name, help),
+                };
+                if let Some((ref choices, ignore_case)) = choices {
+                    match find_choice(choices, ignore_case, &value.text) {
+                        Some(choice) => {
+                            callback(help, Some(choice), &mut settings.output_sink.borrow_mut());
+                            return Ok(Some(name.clone()));
+                        }
+                        None => return invalid_choice(name, format!("--{}", name), &value, choices, None, help),
+                    }
+                }
+                callback(help, Some(&value.text), &mut settings.output_sink.borrow_mut());
+                return Ok(Some(name.clone()));
+            }
+            (ref name, _) if inline.is_some() => {
+                return ParseFailure::new(ErrorKind::UnexpectedValue, 
+                    format!("Option '{}' does not take a value", option), help)
+                    .at(Some(index)).text(option).name(name).fail();
+            }
+            (ref name, &mut Flag { .. }) | (ref name, &mut Tristate(_)) => {
+                staged.flags.insert(name.clone(), true);
+            }
+            (ref name, &mut Count(_)) => {
+                *staged.counts.entry(name.clone()).or_insert(0) += 1;
+            }
+            (ref name, &mut Interrupt { ref mut callback, .. }) => {
+                callback(help, None, &mut settings.output_sink.borrow_mut());
                 return Ok(Some(name.clone()));
             }
         }
         Ok(None)
     }
+    
+    /// Fails on an unknown subcommand, suggesting the closest known names.
+    fn unknown_subcommand<T>(&self, given: &str, index: usize, help: Rc<Help<'def>>) 
+            -> Result<T, ParseError<'def>> {
+        let names = self.subcommands.keys().chain(self.commands.keys())
+            .filter(|name| ! self.hidden.contains(*name))
+            .map(|name| (name.as_ref(), name.to_string()));
+        let aliases = self.aliases.iter()
+            .filter(|&(_, name)| ! self.hidden.contains(name))
+            .map(|(alias, _)| (alias.as_ref(), alias.to_string()));
+        let suggestions = suggest(given, names.chain(aliases));
+        ParseFailure::new(ErrorKind::UnknownSubcommand, format!("Unknown subcommand: '{}'", given), help)
+            .at(Some(index)).text(given).suggest(suggestions).fail()
+    }
+    
+    /// Writes the help of the subcommand at the given path, like `remote add`
+    /// in `git help remote add`, or the help of the program itself if the 
+    /// path is empty. Subcommand handlers are never run for this.
+    fn write_subcommand_help(&self, help: Rc<Help<'def>>, path: &[(usize, &str)], sink: &mut Sink) 
+            -> Result<(), ParseError<'def>> {
+        let mut current: &Help<'def> = &help;
+        let mut description = String::new();
+        for &(index, name) in path.iter() {
+            let sub = match current.get_subcommand_help(name) {
+                Some(sub) => sub,
+                // A `cmd` handler whose arguments aren't described.
+                None if current.subcommands.iter().any(|&(ref n, _)| n == current.resolve_alias(name)) => {
+                    let program = format!("{} {}", current.program, current.resolve_alias(name));
+                    sink.write_str(&format!("No help available for '{}'.\n", program));
+                    return Ok(());
+                }
+                None => {
+                    let names = current.subcommands.iter().map(|&(ref n, _)| (n.as_ref(), n.to_string()));
+                    let suggestions = suggest(name, names);
+                    let msg = format!("Unknown subcommand: '{}'", name);
+                    return ParseFailure::new(ErrorKind::UnknownSubcommand, msg, Rc::new(current.clone()))
+                        .at(Some(index)).text(name).suggest(suggestions).fail();
+                }
+            };
+            let name = current.resolve_alias(name);
+            description = current.subcommands.iter()
+                .find(|&&(ref n, _)| n == name)
+                .and_then(|&(_, ref help)| help.as_ref().map(|h| h.to_string()))
+                .unwrap_or_default();
+            current = sub;
+        }
+        current.write_help_to(sink, &description);
+        Ok(())
+    }
+    
+    /// Reads the fallback value of the named argument, if any. The bound
+    /// environment variable takes precedence over the config file, which
+    /// takes precedence over the default value.
+    ///
+    /// Global options are looked up in the config sections of the enclosing
+    /// commands as well, innermost first, so that `remote.add` falls back to
+    /// `remote` and then to the top-level section.
+    fn read_fallback(&self, name: &str, settings: &ParseSettings, section: &str) 
+            -> Option<(String, ValueSource)> {
+        if let Some(var) = self.env.get(name) {
+            if let Some(value) = settings.var(var) {
+                return Some((value, ValueSource::Environment(var.to_string())));
+            }
+        }
+        if let Some(ref config) = settings.config {
+            let global = self.globals.iter().any(|g| g == name);
+            let mut section = section;
+            loop {
+                if let Some(entry) = config.get(section, name) {
+                    let source = ValueSource::ConfigFile(config.path.clone(), entry.line);
+                    return Some((entry.value.clone(), source));
+                }
+                if ! global || section.is_empty() {
+                    break;
+                }
+                section = match section.rfind('.') {
+                    Some(i) => &section[..i],
+                    None => "",
+                };
+            }
+        }
+        self.defaults.get(name).map(|value| (value.to_string(), ValueSource::Default))
+    }
+    
+    /// Checks that every key in the config section of this parse names an 
+    /// argument that can be read from it.
+    fn check_config(&self, settings: &ParseSettings, section: &str, help: Rc<Help<'def>>,
+            failures: &mut Failures<'def>) -> Result<(), ParseError<'def>> {
+        let config = match settings.config {
+            Some(ref config) => config,
+            None => return Ok(()),
+        };
+        for entry in config.section(section) {
+            let key = entry.key.as_str();
+            let known = match self.options.get(key) {
+                Some(&TargetRef::Interrupt { .. }) => false,
+                Some(_) => true,
+                None => {
+                    self.positional.iter().any(|&(ref name, _)| name == key) ||
+                    self.trail.as_ref().map(|&(ref name, _, _)| name == key).unwrap_or(false)
+                }
+            };
+            if ! known {
+                let source = ValueSource::ConfigFile(config.path.clone(), entry.line);
+                let msg = source.describe(format!("Unknown key '{}'", key));
+                failures.record(ParseFailure::new(ErrorKind::UnknownConfigKey, msg, help.clone())
+                    .text(key).fail())?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Stages values from the environment, the config file or the defaults for
+    /// the arguments that weren't given.
+    fn read_fallbacks(&self, staged: &mut Staged<'def>, settings: &ParseSettings, 
+            section: &str, help: Rc<Help<'def>>, failures: &mut Failures<'def>) 
+            -> Result<(), ParseError<'def>> {
+        while let Some(&(ref name, _)) = self.positional.get(staged.positional.len()) {
+            match self.read_fallback(name, settings, section) {
+                Some((value, source)) => {
+                    staged.positional.push(RawValue::fallback(value));
+                    staged.sources.insert(name.clone(), source);
+                }
+                None => break,
+            }
+        }
+        
+        if let Some((ref name, _, _)) = self.trail {
+            if staged.trail.is_empty() {
+                if let Some((value, source)) = self.read_fallback(name, settings, section) {
+                    staged.trail.push(RawValue::fallback(value));
+                    staged.sources.insert(name.clone(), source);
+                }
+            }
+        }
+        
+        for (name, target) in self.options.iter() {
+            let given = match *target {
+                TargetRef::Flag { .. } | TargetRef::Tristate(_) => staged.flags.contains_key(name),
+                TargetRef::Count(_) => staged.counts.contains_key(name),
+                TargetRef::OptArg(_) => staged.values.contains_key(name),
+                TargetRef::Repeated { .. } => staged.lists.contains_key(name),
+                TargetRef::Interrupt { .. } => true,
+            };
+            if given {
+                continue;
+            }
+            let (value, source) = match self.read_fallback(name, settings, section) {
+                Some(found) => found,
+                None => continue,
+            };
+            match *target {
+                TargetRef::Flag { .. } | TargetRef::Tristate(_) => match parse_env_flag(&value) {
+                    Some(true) => { staged.flags.insert(name.clone(), true); }
+                    // Only negatable flags are turned off by a fallback.
+                    Some(false) if self.is_negatable(name) => { staged.flags.insert(name.clone(), false); }
+                    Some(false) => {}
+                    None => {
+                        let msg = format!("Invalid value '{}' for flag '{}'", value, name);
+                        failures.record(ParseFailure::new(ErrorKind::InvalidValue, source.describe(msg), help.clone())
+                            .text(value).name(name).fail())?;
+                        continue;
+                    }
+                },
+                TargetRef::Count(_) => match value.trim().parse::<usize>() {
+                    Ok(count) => { staged.counts.insert(name.clone(), count); }
+                    Err(err) => {
+                        let msg = format!("Invalid count '{}' for '{}'", value, name);
+                        failures.record(ParseFailure::new(ErrorKind::InvalidValue, source.describe(msg), help.clone())
+                            .text(value).name(name).cause(err.to_string()).fail())?;
+                        continue;
+                    }
+                },
+                TargetRef::Repeated { delimiter, .. } => {
+                    staged.lists.insert(name.clone(), split_values(RawValue::fallback(value), delimiter));
+                }
+                _ => { staged.values.insert(name.clone(), RawValue::fallback(value)); }
+            }
+            staged.sources.insert(name.clone(), source);
+        }
+        Ok(())
+    }
+    
+    /// Checks that every required option has a value.
+    fn check_required(&self, staged: &Staged<'def>, help: Rc<Help<'def>>, 
+            failures: &mut Failures<'def>) -> Result<(), ParseError<'def>> {
+        for name in self.required.iter() {
+            if ! (staged.values.contains_key(name) || staged.lists.contains_key(name)) {
+                failures.record(ParseFailure::new(ErrorKind::MissingOption, 
+                    format!("Missing required option '--{}'", name), help.clone())
+                    .name(name).fail())?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Returns how the named argument is written in messages.
+    fn display_name(&self, name: &str) -> String {
+        if self.options.contains_key(name) || self.globals.iter().any(|g| g == name) {
+            format!("--{}", name)
+        } else {
+            name.to_string()
+        }
+    }
+    
+    /// Returns the staged values that hold the named argument. The values of
+    /// global options are staged by the subcommand that read them last, which
+    /// is one of the levels `below`.
+    fn staged_for<'a>(&self, name: &str, staged: &'a Staged<'def>, below: &[&'a Staged<'def>]) 
+            -> &'a Staged<'def> {
+        if ! self.globals.iter().any(|g| g == name) {
+            return staged;
+        }
+        below.iter().cloned()
+            .find(|level| {
+                level.flags.contains_key(name) || level.counts.contains_key(name) ||
+                level.values.contains_key(name) || level.lists.contains_key(name)
+            })
+            .unwrap_or(staged)
+    }
+    
+    /// Returns whether the named argument or group has been given. Default
+    /// values don't count.
+    fn is_given(&self, name: &str, staged: &Staged<'def>, below: &[&Staged<'def>]) -> bool {
+        if let Some(group) = self.groups.iter().find(|g| g.name == name) {
+            return group.members.iter().any(|member| self.is_given(member, staged, below));
+        }
+        let staged = self.staged_for(name, staged, below);
+        if let Some(&ValueSource::Default) = staged.sources.get(name) {
+            return false;
+        }
+        if let Some(i) = self.positional.iter().position(|&(ref n, _)| n.as_ref() == name) {
+            return i < staged.positional.len();
+        }
+        if let Some((ref n, _, _)) = self.trail {
+            if n.as_ref() == name {
+                return ! staged.trail.is_empty();
+            }
+        }
+        if let Some((ref n, _)) = self.passthrough {
+            if n.as_ref() == name {
+                return ! staged.passthrough.is_empty();
+            }
+        }
+        staged.flags.get(name) == Some(&true) || staged.counts.contains_key(name) || 
+            staged.values.contains_key(name) || staged.lists.contains_key(name)
+    }
+    
+    /// Checks that the groups and the `requires`/`conflicts_with` relations 
+    /// are satisfied. `below` are the values staged by the subcommands, 
+    /// which hold those of the global options.
+    fn check_relations(&self, staged: &Staged<'def>, below: &[&Staged<'def>], help: Rc<Help<'def>>, 
+            failures: &mut Failures<'def>) -> Result<(), ParseError<'def>> {
+        let quoted = |name: &str| {
+            self.staged_for(name, staged, below).describe(name, format!("'{}'", self.display_name(name)))
+        };
+        for group in self.groups.iter() {
+            let given: Vec<_> = group.members.iter()
+                .filter(|member| self.is_given(member, staged, below))
+                .collect();
+            if group.exclusive && given.len() > 1 {
+                let msg = format!("{} and {} cannot be used together", quoted(given[0]), quoted(given[1]));
+                failures.record(ParseFailure::new(ErrorKind::ArgumentConflict, msg, help.clone())
+                    .name(&group.name).fail())?;
+            }
+            if group.required && given.is_empty() {
+                let members: Vec<_> = group.members.iter()
+                    .map(|member| format!("'{}'", self.display_name(member)))
+                    .collect();
+                let msg = format!("One of {} is required", members.join(", "));
+                failures.record(ParseFailure::new(ErrorKind::MissingRequirement, msg, help.clone())
+                    .name(&group.name).fail())?;
+            }
+        }
+        for &(ref name, ref requires, ref conflicts) in self.relations.iter() {
+            if ! self.is_given(name, staged, below) {
+                continue;
+            }
+            for other in requires.iter() {
+                if ! self.is_given(other, staged, below) {
+                    let msg = format!("{} requires '{}'", quoted(name), self.display_name(other));
+                    failures.record(ParseFailure::new(ErrorKind::MissingRequirement, msg, help.clone())
+                        .name(name).fail())?;
+                }
+            }
+            for other in conflicts.iter() {
+                if self.is_given(other, staged, below) {
+                    let msg = format!("{} cannot be used with {}", quoted(name), quoted(other));
+                    failures.record(ParseFailure::new(ErrorKind::ArgumentConflict, msg, help.clone())
+                        .name(name).fail())?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Replaces the staged value of an argument with choices by the choice it
+    /// matches, and rejects it if it matches none.
+    fn match_choice(&self, name: &str, value: &mut RawValue, 
+            sources: &HashMap<Cow<'def, str>, ValueSource>, help: &Rc<Help<'def>>) 
+            -> Result<(), ParseError<'def>> {
+        let &(ref choices, ignore_case) = match self.choices.get(name) {
+            Some(choices) => choices,
+            None => return Ok(()),
+        };
+        let choice = match find_choice(choices, ignore_case, &value.text) {
+            Some(choice) => choice.to_string(),
+            None => {
+                return invalid_choice(name, self.display_name(name), value, choices, 
+                    sources.get(name), help.clone());
+            }
+        };
+        value.text = choice;
+        Ok(())
+    }
+    
+    /// Checks the staged values of every argument with choices.
+    fn check_choices(&self, staged: &mut Staged<'def>, help: Rc<Help<'def>>, 
+            failures: &mut Failures<'def>) -> Result<(), ParseError<'def>> {
+        for (&(ref name, _), value) in self.positional.iter().zip(staged.positional.iter_mut()) {
+            failures.record(self.match_choice(name, value, &staged.sources, &help))?;
+        }
+        if let Some((ref name, _, _)) = self.trail {
+            for value in staged.trail.iter_mut() {
+                failures.record(self.match_choice(name, value, &staged.sources, &help))?;
+            }
+        }
+        for (name, value) in staged.values.iter_mut() {
+            failures.record(self.match_choice(name, value, &staged.sources, &help))?;
+        }
+        for (name, values) in staged.lists.iter_mut() {
+            for value in values.iter_mut() {
+                failures.record(self.match_choice(name, value, &staged.sources, &help))?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Fails on a value that was rejected by its target.
+    fn invalid_value<T>(&self, name: &str, value: &RawValue, cause: String, 
+            staged: &Staged<'def>, help: Rc<Help<'def>>) -> Result<T, ParseError<'def>> {
+        let msg = format!("Invalid value '{}' for '{}': {}", value.text, self.display_name(name), cause);
+        ParseFailure::new(ErrorKind::InvalidValue, staged.describe(name, msg), help)
+            .at(value.index).text(value.text.clone()).name(name).cause(cause).fail()
+    }
+    
+    /// Has every staged value parsed by its target, keeping the results in 
+    /// `staged.prepared` for `commit`. Values read from arguments that aren't
+    /// valid UTF-8 are given to the targets as they were passed.
+    fn prepare(&self, staged: &mut Staged<'def>, originals: &[Option<OsString>], help: Rc<Help<'def>>, 
+            failures: &mut Failures<'def>) -> Result<(), ParseError<'def>> {
+        let mut prepared = Vec::new();
+        for (i, (&(ref name, ref target), value)) in self.positional.iter().zip(staged.positional.iter()).enumerate() {
+            if let Some(ref target) = *target {
+                let result = match value.original(originals) {
+                    Some(original) => target.prepare_raw(&original),
+                    None => target.prepare(&value.text),
+                };
+                match result {
+                    Ok(value) => prepared.push((Slot::Positional(i), value)),
+                    Err(cause) => failures.record(self.invalid_value(name, value, cause, staged, help.clone()))?,
+                }
+            }
+        }
+        if let Some((ref name, _, Some(ref target))) = self.trail {
+            match prepare_all(&**target, &staged.trail, originals) {
+                Ok(values) => prepared.push((Slot::Trail, values)),
+                Err((i, cause)) => {
+                    failures.record(self.invalid_value(name, &staged.trail[i], cause, staged, help.clone()))?
+                }
+            }
+        }
+        for (name, value) in staged.values.iter() {
+            if let Some(&TargetRef::OptArg(Some(ref target))) = self.options.get(name) {
+                let result = match value.original(originals) {
+                    Some(original) => target.prepare_raw(&original),
+                    None => target.prepare(&value.text),
+                };
+                match result {
+                    Ok(value) => prepared.push((Slot::Option(name.clone()), value)),
+                    Err(cause) => failures.record(self.invalid_value(name, value, cause, staged, help.clone()))?,
+                }
+            }
+        }
+        for (name, values) in staged.lists.iter() {
+            if let Some(&TargetRef::Repeated { target: Some(ref target), .. }) = self.options.get(name) {
+                match prepare_all(&**target, values, originals) {
+                    Ok(values) => prepared.push((Slot::Option(name.clone()), values)),
+                    Err((i, cause)) => {
+                        failures.record(self.invalid_value(name, &values[i], cause, staged, help.clone()))?
+                    }
+                }
+            }
+        }
+        staged.prepared = prepared;
+        Ok(())
+    }
+    
+    /// Writes the values that `prepare` produced to their targets, along 
+    /// with the staged flags, counts and passed through arguments.
+    fn commit(&mut self, staged: Staged<'def>) {
+        for (slot, value) in staged.prepared.into_iter() {
+            match slot {
+                Slot::Positional(i) => {
+                    if let Some(ref mut target) = self.positional[i].1 {
+                        target.commit(value);
+                    }
+                }
+                Slot::Trail => {
+                    if let Some((_, _, Some(ref mut target))) = self.trail {
+                        target.commit(value);
+                    }
+                }
+                Slot::Option(name) => match self.options.get_mut(&name) {
+                    Some(&mut TargetRef::OptArg(Some(ref mut target))) => target.commit(value),
+                    Some(&mut TargetRef::Repeated { target: Some(ref mut target), .. }) => target.commit(value),
+                    _ => {}
+                },
+            }
+        }
+        if let Some((_, Some(ref mut target))) = self.passthrough {
+            target.extend(staged.passthrough.iter().cloned());
+        }
+        for (name, target) in self.options.iter_mut() {
+            match *target {
+                TargetRef::Flag { target: Some(ref mut flag), .. } => {
+                    if let Some(&value) = staged.flags.get(name) {
+                        **flag = value;
+                    }
+                }
+                TargetRef::Tristate(Some(ref mut flag)) => {
+                    if let Some(&value) = staged.flags.get(name) {
+                        **flag = Some(value);
+                    }
+                }
+                TargetRef::Count(Some(ref mut count)) => {
+                    if let Some(n) = staged.counts.get(name) {
+                        **count += *n;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    /// Collects the staged values by name, along with their sources.
+    fn matches(&self, staged: &Staged<'def>, subcommand: Option<(String, Matches)>) -> Matches {
+        let mut values = HashMap::new();
+        let mut occurrences = HashMap::new();
+        let texts = |values: &[RawValue]| values.iter().map(|v| v.text.clone()).collect::<Vec<_>>();
+        for (&(ref name, _), value) in self.positional.iter().zip(staged.positional.iter()) {
+            values.insert(name.to_string(), vec![value.text.clone()]);
+        }
+        if let Some((ref name, _, _)) = self.trail {
+            if ! staged.trail.is_empty() {
+                values.insert(name.to_string(), texts(&staged.trail));
+            }
+        }
+        if let Some((ref name, _)) = self.passthrough {
+            if ! staged.passthrough.is_empty() {
+                values.insert(name.to_string(), staged.passthrough.clone());
+            }
+        }
+        for (name, value) in staged.values.iter() {
+            values.insert(name.to_string(), vec![value.text.clone()]);
+        }
+        for (name, list) in staged.lists.iter() {
+            values.insert(name.to_string(), texts(list));
+        }
+        for (name, &value) in staged.flags.iter() {
+            if value {
+                occurrences.insert(name.to_string(), 1);
+            }
+            // Negatable flags have a value, so that turning them off can be
+            // told apart from not giving them.
+            if self.is_negatable(name) {
+                values.insert(name.to_string(), vec![value.to_string()]);
+            }
+        }
+        for (name, &count) in staged.counts.iter() {
+            occurrences.insert(name.to_string(), count);
+        }
+        let sources = staged.sources.iter()
+            .map(|(name, source)| (name.to_string(), source.clone()))
+            .collect();
+        matches::assemble(values, occurrences, sources, subcommand)
+    }
+}
+
+thread_local! {
+    /// The matches recorded for each running subcommand handler, innermost
+    /// last.
+    static CAPTURES: RefCell<Vec<Option<Matches>>> = RefCell::new(Vec::new());
+}
+
+/// Runs a subcommand handler, returning its result along with the matches of
+/// the first `parse_matches` it ran, if any.
+fn capture<T, F: FnOnce() -> T>(f: F) -> (T, Option<Matches>) {
+    CAPTURES.with(|captures| captures.borrow_mut().push(None));
+    let result = f();
+    (result, CAPTURES.with(|captures| captures.borrow_mut().pop().unwrap()))
+}
+
+/// Records the matches of a successful `parse_matches` for the handler running
+/// it.
+fn record_capture(matches: &Matches) {
+    CAPTURES.with(|captures| {
+        if let Some(recorded) = captures.borrow_mut().last_mut() {
+            if recorded.is_none() {
+                *recorded = Some(matches.clone());
+            }
+        }
+    })
 }
 
 fn validate_short<'def, N: AsRef<str>>(name: &N) -> Result<(), ParseError<'def>> {
@@ -98,99 +1089,264 @@ fn validate_short<'def, N: AsRef<str>>(name: &N) -> Result<(), ParseError<'def>>
 }
 
 /// Sorts the given definitions and checks that all invariants are upheld.
-pub fn parse_definitions<'def, 'tar>(defs: Vec<ArgDef<'def, 'tar>>) 
+pub fn parse_definitions<'def, 'tar>(defs: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+        -> Result<ParseState<'def, 'tar>, ParseError<'def>> {
+    sort_definitions(defs, settings, false)
+}
+
+/// Like `parse_definitions`, but checks the definitions of the subcommands
+/// defined by `ArgDef::subcommand` as well, so that they can be parsed with
+/// `parse_plain_checked` from then on.
+pub fn check_definitions<'def, 'tar>(defs: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+        -> Result<(), ParseError<'def>> {
+    let state = parse_definitions(defs, settings)?;
+    for (_, children) in state.commands {
+        check_definitions(children, settings)?;
+    }
+    Ok(())
+}
+
+/// Sorts the given definitions, leaving out the checks of `parse_definitions`
+/// if they are known to pass already.
+fn sort_definitions<'def, 'tar>(defs: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings, checked: bool) 
         -> Result<ParseState<'def, 'tar>, ParseError<'def>> {
-    let mut positional = VecDeque::new();
+    let mut positional = Vec::new();
     let mut trail = None;
+    let mut passthrough = None;
     let mut options = HashMap::new(); // long-to-arg
     let mut short_map = HashMap::new(); // short-to-long
     let mut subcommands = HashMap::new();
+    let mut commands = HashMap::new();
+    let mut env = HashMap::new(); // name-to-variable
+    let mut defaults = HashMap::new(); // name-to-value
+    let mut has_optional_positional = false;
+    let mut required = Vec::new();
+    let mut choices = HashMap::new(); // name-to-(choices, ignore case)
+    let mut groups = Vec::new();
+    let mut relations = Vec::new();
+    let mut globals = Vec::new();
+    let mut aliases = HashMap::new(); // alias-to-name
+    let mut hidden = HashSet::new();
     let mut has_positional = false;
     let mut has_subcommand = false;
+    let prefix = settings.env_prefix.as_ref().map(|p| p.as_str());
     for def in defs {
+        if let Some(var) = def.env_var(prefix) {
+            env.insert(def.name.clone(), var);
+        }
+        if let Some(ref value) = def.default {
+            defaults.insert(def.name.clone(), value.clone());
+        }
+        if ! def.choices.is_empty() {
+            choices.insert(def.name.clone(), (def.choices, def.ignore_case));
+        }
+        if ! (def.requires.is_empty() && def.conflicts.is_empty()) {
+            relations.push((def.name.clone(), def.requires, def.conflicts));
+        }
+        if def.global {
+            globals.push(def.name.clone());
+        }
+        if def.hidden {
+            hidden.insert(def.name.clone());
+        }
+        for alias in def.aliases {
+            if ! checked && aliases.contains_key(&alias) {
+                return ParseError::defs(format!("Alias '{}' defined twice.", alias));
+            }
+            aliases.insert(alias, def.name.clone());
+        }
         match def.kind {
             ArgDefKind::Positional { target } => {
-                if has_subcommand {
+                if ! checked && has_subcommand {
                     return ParseError::defs(format!("Positional (+trail) and subcommand definitions cannot be used together."));
                 }
                 has_positional = true;
-                positional.push_back((def.name, target));
+                if def.default.is_some() {
+                    has_optional_positional = true;
+                } else if ! checked && has_optional_positional {
+                    return ParseError::defs(format!("Positional '{}' must have a default, since it follows one with a default.", def.name));
+                }
+                positional.push((def.name, target));
             }
             ArgDefKind::Trail { optional, target } => {
-                if has_subcommand {
+                if ! checked && has_subcommand {
                     return ParseError::defs(format!("Positional (+trail) and subcommand definitions cannot be used together."));
                 }
                 has_positional = true;
-                if trail.is_some() {
+                if ! checked && trail.is_some() {
                     return ParseError::defs(format!("Two trails defined."));
                 }
                 trail = Some((def.name, optional, target));
             }
-            ArgDefKind::Subcommand { handler } => {
-                if has_positional {
+            ArgDefKind::Group { members, exclusive, required } => {
+                let name = def.name;
+                if ! checked && groups.iter().any(|g: &Group| g.name == name) {
+                    return ParseError::defs(format!("Group '{}' defined twice.", name));
+                }
+                groups.push(Group { name, members, exclusive, required });
+            }
+            ArgDefKind::Passthrough { target } => {
+                if ! checked && passthrough.is_some() {
+                    return ParseError::defs(format!("Two passthrough arguments defined."));
+                }
+                passthrough = Some((def.name, target));
+            }
+            ArgDefKind::Subcommand { handler, .. } => {
+                if ! checked && has_positional {
                     return ParseError::defs(format!("Positional (+trail) and subcommand definitions cannot be used together."));
                 }
                 has_subcommand = true;
-                if subcommands.contains_key(&def.name) {
+                if ! checked && (subcommands.contains_key(&def.name) || commands.contains_key(&def.name)) {
                     return ParseError::defs(format!("Sucommand '{}' defined twice", def.name))
                 }
                 subcommands.insert(def.name, handler);
             }
-            ArgDefKind::Flag { short, target } => {
+            ArgDefKind::Command { children } => {
+                if ! checked && has_positional {
+                    return ParseError::defs(format!("Positional (+trail) and subcommand definitions cannot be used together."));
+                }
+                has_subcommand = true;
+                if ! checked && (subcommands.contains_key(&def.name) || commands.contains_key(&def.name)) {
+                    return ParseError::defs(format!("Sucommand '{}' defined twice", def.name))
+                }
+                commands.insert(def.name, children);
+            }
+            ArgDefKind::Flag { short, target, negatable } => {
+                if let Some(short) = short {
+                    if ! checked {
+                        validate_short(&short)?;
+                    }
+                    if ! checked && short_map.contains_key(&short) {
+                        return ParseError::defs(format!("Short name '{}' defined twice.", short));
+                    }
+                    short_map.insert(short, def.name.clone());
+                }
+                if ! checked && options.contains_key(&def.name) {
+                    return ParseError::defs(format!("Option '{}' defined twice.", def.name));
+                }
+                options.insert(def.name, TargetRef::Flag { target, negatable });
+            }
+            ArgDefKind::Tristate { short, target } => {
                 if let Some(short) = short {
-                    validate_short(&short)?;
-                    if short_map.contains_key(&short) {
+                    if ! checked {
+                        validate_short(&short)?;
+                    }
+                    if ! checked && short_map.contains_key(&short) {
                         return ParseError::defs(format!("Short name '{}' defined twice.", short));
                     }
                     short_map.insert(short, def.name.clone());
                 }
-                if options.contains_key(&def.name) {
+                if ! checked && options.contains_key(&def.name) {
                     return ParseError::defs(format!("Option '{}' defined twice.", def.name));
                 }
-                options.insert(def.name, TargetRef::Flag(target));
+                options.insert(def.name, TargetRef::Tristate(target));
             }
             ArgDefKind::Count { short, target } => {
                 if let Some(short) = short {
-                    validate_short(&short)?;
-                    if short_map.contains_key(&short) {
+                    if ! checked {
+                        validate_short(&short)?;
+                    }
+                    if ! checked && short_map.contains_key(&short) {
                         return ParseError::defs(format!("Short name '{}' defined twice.", short));
                     }
                     short_map.insert(short, def.name.clone());
                 }
-                if options.contains_key(&def.name) {
+                if ! checked && options.contains_key(&def.name) {
                     return ParseError::defs(format!("Option '{}' defined twice.", def.name));
                 }
                 options.insert(def.name, TargetRef::Count(target));
             }
-            ArgDefKind::OptArg { short, target } => {
+            ArgDefKind::OptArg { short, target, required: is_required } => {
                 if let Some(short) = short {
-                    validate_short(&short)?;
-                    if short_map.contains_key(&short) {
+                    if ! checked {
+                        validate_short(&short)?;
+                    }
+                    if ! checked && short_map.contains_key(&short) {
                         return ParseError::defs(format!("Short name '{}' defined twice.", short));
                     }
                     short_map.insert(short, def.name.clone());
                 }
-                if options.contains_key(&def.name) {
+                if ! checked && options.contains_key(&def.name) {
                     return ParseError::defs(format!("Option '{}' defined twice.", def.name));
                 }
+                if is_required {
+                    required.push(def.name.clone());
+                }
                 options.insert(def.name, TargetRef::OptArg(target));
             }
-            ArgDefKind::Interrupt { short, callback } => {
+            ArgDefKind::Repeated { short, target, delimiter, required: is_required } => {
+                if let Some(short) = short {
+                    if ! checked {
+                        validate_short(&short)?;
+                    }
+                    if ! checked && short_map.contains_key(&short) {
+                        return ParseError::defs(format!("Short name '{}' defined twice.", short));
+                    }
+                    short_map.insert(short, def.name.clone());
+                }
+                if ! checked && options.contains_key(&def.name) {
+                    return ParseError::defs(format!("Option '{}' defined twice.", def.name));
+                }
+                if is_required {
+                    required.push(def.name.clone());
+                }
+                options.insert(def.name, TargetRef::Repeated { target, delimiter });
+            }
+            ArgDefKind::Interrupt { short, callback, value_name } => {
                 if let Some(short) = short {
-                    validate_short(&short)?;
-                    if short_map.contains_key(&short) {
+                    if ! checked {
+                        validate_short(&short)?;
+                    }
+                    if ! checked && short_map.contains_key(&short) {
                         return ParseError::defs(format!("Short name '{}' defined twice.", short));
                     }
                     short_map.insert(short, def.name.clone());
                 }
-                if options.contains_key(&def.name) {
+                if ! checked && options.contains_key(&def.name) {
                     return ParseError::defs(format!("Option '{}' defined twice.", def.name));
                 }
-                options.insert(def.name, TargetRef::Interrupt(callback));
+                options.insert(def.name, TargetRef::Interrupt {
+                    callback, 
+                    takes_value: value_name.is_some(), 
+                });
+            }
+        }
+    }
+    
+    // The checks that need every definition.
+    if ! checked {
+        for (alias, name) in aliases.iter() {
+            if subcommands.contains_key(alias) || commands.contains_key(alias) {
+                return ParseError::defs(format!("Alias '{}' of '{}' is the name of a subcommand.", alias, name));
+            }
+        }
+        
+        {
+            let is_argument = |name: &str| {
+                options.contains_key(name) ||
+                positional.iter().any(|&(ref n, _)| n.as_ref() == name) ||
+                trail.as_ref().map(|&(ref n, _, _)| n.as_ref() == name).unwrap_or(false) ||
+                passthrough.as_ref().map(|&(ref n, _)| n.as_ref() == name).unwrap_or(false)
+            };
+            for group in groups.iter() {
+                for member in group.members.iter() {
+                    if ! is_argument(member) {
+                        return ParseError::defs(format!("Group '{}' has an unknown member '{}'.", group.name, member));
+                    }
+                }
+            }
+            for &(ref name, ref requires, ref conflicts) in relations.iter() {
+                for other in requires.iter().chain(conflicts.iter()) {
+                    if ! (is_argument(other) || groups.iter().any(|g| g.name == *other)) {
+                        return ParseError::defs(format!("'{}' refers to an unknown argument '{}'.", name, other));
+                    }
+                }
             }
         }
     }
-    Ok(ParseState { positional, trail, subcommands, options, short_map })
+    
+    Ok(ParseState { positional, trail, passthrough, subcommands, commands, aliases, options, short_map, 
+        env, defaults, required, choices, groups, relations, globals, hidden })
 }
 
 #[derive(Debug)]
@@ -198,28 +1354,196 @@ pub enum ParseError<'def> {
     /// The given argument definitions aren't valid.
     InvalidDefinitions(String),
     
-    /// The parse could not finish succesfully.
-    ParseFailed(String, Rc<Help<'def>>),
+    /// The parse could not finish succesfully. No targets have been modified.
+    ParseFailed(ParseFailure<'def>),
+    
+    /// Like `ParseFailed`, but with every problem found, in the order of the
+    /// arguments they are in. Those that aren't in an argument, like missing
+    /// ones, come last. Only given when `ParseSettings::collect_errors` is set.
+    ParseFailures(Vec<ParseFailure<'def>>),
     
     /// A subcommand failed to parse, and has been handled.
     SubParseFailed,
     
     /// An interrupt-flag with the given name was encountered.
     /// 
-    /// The variables pointed to by the definitions will not all have been
-    /// assigned their expected values.
+    /// The variables pointed to by the definitions will not have been
+    /// modified.
     Interrupted(Cow<'def, str>)
 }
 
+/// What kind of problem made a parse fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An option that isn't defined. `--verbos`
+    UnknownOption,
+    /// A subcommand that isn't defined.
+    UnknownSubcommand,
+    /// A config file key that doesn't name an argument.
+    UnknownConfigKey,
+    /// An option was given without its value. `--output` at the end
+    MissingValue,
+    /// An option that takes no value was given one. `--verbose=yes`
+    UnexpectedValue,
+    /// A value was rejected by its target.
+    InvalidValue,
+    /// A value isn't one of the choices of its argument.
+    InvalidChoice,
+    /// An option that takes a single value was given twice.
+    DuplicateOption,
+    /// A positional argument or trail wasn't given.
+    MissingPositional,
+    /// A required option wasn't given.
+    MissingOption,
+    /// No subcommand was given.
+    MissingSubcommand,
+    /// An argument was given that no definition reads.
+    UnexpectedArgument,
+    /// An argument or group required by a group or another argument wasn't
+    /// given.
+    MissingRequirement,
+    /// Arguments that cannot be used together were given.
+    ArgumentConflict,
+    /// A response file could not be read or split into arguments. `@args.txt`
+    ResponseFile,
+    /// A command line given as text has an unclosed quote or a trailing
+    /// backslash.
+    InvalidQuoting,
+}
+
+/// The description of a failed parse.
+#[derive(Debug, Clone)]
+pub struct ParseFailure<'def> {
+    pub kind: ErrorKind,
+    /// A message describing the problem.
+    pub message: String,
+    /// The index of the offending argument, if the problem is in one. 
+    /// Subcommands given by `ArgDef::cmd` parse their arguments themselves,
+    /// so they count from the argument after their name. Words read from a
+    /// response file have the index of the `@file` argument, and the text
+    /// of the word.
+    pub index: Option<usize>,
+    /// The offending text, like an unknown option or a rejected value.
+    pub text: Option<String>,
+    /// The name of the definition the problem is about, if any.
+    pub name: Option<String>,
+    /// The error given by the target, like the `FromStr` error of a value.
+    pub cause: Option<String>,
+    /// For unknown options and subcommands, the known names closest to what 
+    /// was given, as they would be written, like `--verbose` or `remote`. 
+    /// The closest come first.
+    pub suggestions: Vec<String>,
+    /// The help of the program that failed to parse.
+    pub help: Rc<Help<'def>>,
+}
+
+impl<'def> ParseFailure<'def> {
+    fn new<S: Into<String>>(kind: ErrorKind, message: S, help: Rc<Help<'def>>) -> ParseFailure<'def> {
+        ParseFailure {
+            kind,
+            message: message.into(),
+            index: None,
+            text: None,
+            name: None,
+            cause: None,
+            suggestions: Vec::new(),
+            help,
+        }
+    }
+    
+    fn at(mut self, index: Option<usize>) -> Self {
+        self.index = index;
+        self
+    }
+    
+    fn text<S: Into<String>>(mut self, text: S) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+    
+    fn name<S: AsRef<str>>(mut self, name: S) -> Self {
+        self.name = Some(name.as_ref().to_string());
+        self
+    }
+    
+    fn cause(mut self, cause: String) -> Self {
+        self.cause = Some(cause);
+        self
+    }
+    
+    /// Adds the suggestions, mentioning them in the message as well.
+    fn suggest(mut self, suggestions: Vec<String>) -> Self {
+        if ! suggestions.is_empty() {
+            let quoted: Vec<_> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+            self.message.push_str(&format!(". Did you mean {}?", quoted.join(" or ")));
+        }
+        self.suggestions = suggestions;
+        self
+    }
+    
+    fn fail<T>(self) -> Result<T, ParseError<'def>> {
+        Err(ParseError::ParseFailed(self))
+    }
+}
+
+/// The failures found so far in a parse.
+struct Failures<'def> {
+    collect: bool,
+    found: Vec<ParseFailure<'def>>,
+    /// The index of the argument that each of the expanded arguments is or
+    /// was read from, if any were read from response files.
+    positions: Vec<usize>,
+}
+
+impl<'def> Failures<'def> {
+    fn new(settings: &ParseSettings, positions: &[usize]) -> Failures<'def> {
+        Failures { collect: settings.collect_errors, found: Vec::new(), positions: positions.to_vec() }
+    }
+    
+    /// Records the outcome of a step. A failure is returned right away, 
+    /// unless every failure is collected.
+    fn record(&mut self, result: Result<(), ParseError<'def>>) -> Result<(), ParseError<'def>> {
+        match result {
+            Err(ParseError::ParseFailed(mut failure)) => {
+                // Failures point at the arguments as they were given.
+                if let Some(index) = failure.index {
+                    failure.index = Some(self.positions.get(index).cloned().unwrap_or(index));
+                }
+                if ! self.collect {
+                    return failure.fail();
+                }
+                self.found.push(failure);
+                Ok(())
+            }
+            other => other,
+        }
+    }
+    
+    /// Fails with every collected failure, if there are any, ordered by the
+    /// argument they are in. Values are only checked once every argument has
+    /// been read, so they are found after the unknown options that follow 
+    /// them.
+    fn finish(&mut self) -> Result<(), ParseError<'def>> {
+        if self.found.is_empty() {
+            return Ok(());
+        }
+        let mut found: Vec<_> = self.found.drain(..).collect();
+        found.sort_by_key(|failure| (failure.index.is_none(), failure.index));
+        Err(ParseError::ParseFailures(found))
+    }
+}
+
+impl<'def> fmt::Display for ParseFailure<'def> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
 impl<'def> ParseError<'def> {
     fn defs<T, S: Into<String>>(reason: S) -> Result<T, ParseError<'def>> {
         Err(ParseError::InvalidDefinitions(reason.into()))
     }
     
-    fn parse<T, S: Into<String>>(reason: S, help: Rc<Help<'def>>) -> Result<T, ParseError<'def>> {
-        Err(ParseError::ParseFailed(reason.into(), help))
-    }
-    
     fn interrupt<T>(name: Cow<'def, str>) -> Result<T, ParseError<'def>> {
        Err( ParseError::Interrupted(name))
     }
@@ -231,71 +1555,322 @@ impl<'def> ParseError<'def> {
 pub fn parse_plain<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], definitions: Vec<ArgDef<'def, 'tar>>) 
     -> Result<(), ParseError<'def>>
   where T: Borrow<str> 
+{ 
+    parse_plain_with(program, args, definitions, &ParseSettings::new())
+}
+
+/// Like `parse_plain`, but with the given settings.
+pub fn parse_plain_with<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], 
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+  where T: Borrow<str> 
+{ 
+    run(program, args, Vec::new(), definitions, settings, false).map(|_| ())
+}
+
+/// Parses a command line given as text, like one typed into a console. The
+/// text is split into arguments like a shell would, see
+/// `ParseSettings::response_files`.
+///
+/// Errors are given like in `parse_plain`, with the index of a failure being
+/// that of the word in the text.
+///
+/// # Example
+/// ```
+/// # use playground::*;
+/// let mut a = String::new();
+/// let mut b: Option<String> = None;
+/// parse_str("prog", "a --b 'c d'", vec![ArgDef::pos("a", &mut a), ArgDef::option("b", &mut b)]).unwrap();
+/// assert_eq!(Some("c d".to_string()), b);
+/// ```
+pub fn parse_str<'def, 'tar, P: Into<String>>(program: P, line: &str, 
+        definitions: Vec<ArgDef<'def, 'tar>>) 
+    -> Result<(), ParseError<'def>>
+{ 
+    parse_str_with(program, line, definitions, &ParseSettings::new())
+}
+
+/// Like `parse_str`, but with the given settings.
+pub fn parse_str_with<'def, 'tar, P: Into<String>>(program: P, line: &str, 
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+{ 
+    let program = program.into();
+    let args = split_line(&program, line, &definitions, settings)?;
+    parse_plain_with(program, &args, definitions, settings)
+}
+
+/// Splits a command line given to `parse_str` into arguments.
+pub fn split_line<'def, 'tar>(program: &str, line: &str, definitions: &[ArgDef<'def, 'tar>], 
+        settings: &ParseSettings) 
+    -> Result<Vec<String>, ParseError<'def>>
+{
+    match response::split(line) {
+        Ok(words) => Ok(words.into_iter().map(|(word, _)| word).collect()),
+        Err((_, reason)) => {
+            let help = Rc::new(Help::with_settings(program.to_string(), definitions, settings));
+            ParseFailure::new(ErrorKind::InvalidQuoting, reason, help).fail()
+        }
+    }
+}
+
+/// Parses the given arguments without writing to any targets, and gives the
+/// values that were read by name instead. Arguments are defined with the
+/// `unbound_*` constructors of `ArgDef`, though bound ones are written to as
+/// well.
+///
+/// Errors are given like in `parse_plain`.
+pub fn parse_matches<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], 
+        definitions: Vec<ArgDef<'def, 'tar>>) 
+    -> Result<Matches, ParseError<'def>>
+  where T: Borrow<str> 
+{ 
+    parse_matches_with(program, args, definitions, &ParseSettings::new())
+}
+
+/// Like `parse_matches`, but with the given settings.
+pub fn parse_matches_with<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], 
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<Matches, ParseError<'def>>
+  where T: Borrow<str> 
+{ 
+    let matches = run(program, args, Vec::new(), definitions, settings, false)?;
+    record_capture(&matches);
+    Ok(matches)
+}
+
+/// Like `parse_plain_with`, but for definitions that `check_definitions` has
+/// accepted, which aren't checked again. The originals are given like in
+/// `run`.
+pub fn parse_plain_checked<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], 
+        originals: Vec<Option<OsString>>, definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+  where T: Borrow<str> 
+{ 
+    run(program, args, originals, definitions, settings, true).map(|_| ())
+}
+
+/// Parses the given arguments, updates the defined variables with them and
+/// returns the matches of the parse.
+///
+/// The originals are those of the arguments that aren't valid UTF-8, by 
+/// index, when parsing the arguments given to `parse_os`. The definitions
+/// are only checked if they aren't `checked` already.
+fn run<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], originals: Vec<Option<OsString>>,
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings, checked: bool) 
+    -> Result<Matches, ParseError<'def>>
+  where T: Borrow<str> 
 { 
     let program = program.into();
-    let help = Rc::new(Help::new(program.clone(), &definitions));
-    let mut defs = parse_definitions(definitions)?;
+    let args: Vec<&str> = args.iter().map(|e| e.borrow()).collect();
+    let expanded;
+    let mut positions = Vec::new();
+    let (args, originals) = if settings.response_files {
+        expanded = match response::expand(&args) {
+            Ok(expanded) => expanded,
+            Err((index, msg)) => {
+                let help = Rc::new(Help::with_settings(program, &definitions, settings));
+                return ParseFailure::new(ErrorKind::ResponseFile, msg, help)
+                    .at(Some(index)).text(args[index]).fail();
+            }
+        };
+        // The words read from response files are always valid UTF-8.
+        let moved = expanded.iter()
+            .map(|&(_, index, given)| if given { originals.get(index).cloned().unwrap_or(None) } else { None })
+            .collect();
+        positions = expanded.iter().map(|&(_, index, _)| index).collect();
+        (expanded.iter().map(|&(ref word, _, _)| word.as_str()).collect(), moved)
+    } else {
+        (args, originals)
+    };
+    let (matches, pending) = run_level(program, &args, &originals, &positions, definitions, None, settings, checked)?;
+    for (mut defs, staged) in pending {
+        defs.commit(staged);
+    }
+    Ok(matches)
+}
+
+/// What a subcommand defined by `ArgDef::subcommand` gets from the commands
+/// above it.
+struct Inherited<'def, 'tar> {
+    /// The global options, and the values staged for them so far.
+    globals: (ParseState<'def, 'tar>, Staged<'def>),
+    help: Vec<OptHelp<'def>>,
+    /// The index of the first argument of the subcommand among all of them.
+    offset: usize,
+}
+
+/// Definitions whose staged values are written once every level has parsed.
+type Pending<'def, 'tar> = Vec<(ParseState<'def, 'tar>, Staged<'def>)>;
+
+/// Parses the arguments of a single command, and those of the subcommands
+/// defined by `ArgDef::subcommand` after it. Nothing is written to the
+/// targets of those, they are returned to be committed instead.
+///
+/// The positions give the argument that each of the expanded arguments is
+/// or was read from, when response files have been expanded.
+fn run_level<'def, 'tar>(program: String, args: &[&str], originals: &[Option<OsString>], 
+        positions: &[usize], definitions: Vec<ArgDef<'def, 'tar>>, inherited: Option<Inherited<'def, 'tar>>, 
+        settings: &ParseSettings, checked: bool) 
+    -> Result<(Matches, Pending<'def, 'tar>), ParseError<'def>>
+{ 
+    let mut staged = Staged::default();
+    let help = match inherited {
+        Some(ref inherited) => Help::with_inherited(program.clone(), &definitions, settings, &inherited.help),
+        None => Help::with_settings(program.clone(), &definitions, settings),
+    };
+    let help = Rc::new(help);
+    let mut defs = sort_definitions(definitions, settings, checked)?;
+    let offset = inherited.as_ref().map(|inherited| inherited.offset).unwrap_or(0);
+    if let Some(Inherited { globals: (globals, moved), .. }) = inherited {
+        defs.merge_globals(globals, &mut staged, moved)?;
+    }
+    
+    let mut failures = Failures::new(settings, positions);
+    let section = config::section_of(&program);
+    defs.check_config(settings, &section, help.clone(), &mut failures)?;
     
     //println!("Defs: {:?}", defs);
-    let mut args = args.iter().map(|e| e.borrow());
+    let mut args = args.iter().cloned().enumerate().map(|(i, arg)| (offset + i, arg));
     
-    // value-type definitions that have been given and should not be overridden
-    let mut given_values = HashSet::new();
+    // Whether `--` has been seen, so that no more options are read.
+    let mut options_ended = false;
     
-    while let Some(arg) = args.next() {
+    while let Some((index, arg)) = args.next() {
+        // Passthrough
+        if arg == "--" && ! options_ended {
+            if defs.passthrough.is_some() {
+                // The positionals without a default that are still missing
+                // come first, like `-x` in `tool -- -x`.
+                while staged.positional.len() < defs.positional.len() {
+                    let name = &defs.positional[staged.positional.len()].0;
+                    if defs.defaults.contains_key(name) {
+                        break;
+                    }
+                    match args.next() {
+                        Some((index, arg)) => staged.positional.push(RawValue::given(arg, index)),
+                        None => break,
+                    }
+                }
+                staged.passthrough.extend(args.by_ref().map(|(_, arg)| arg.to_string()));
+            }
+            options_ended = true;
+        
         // Option / interrupt
-        if arg.starts_with("-") {
-            if let Some(interrupt) = defs.read_option(arg, &mut args, &mut given_values, help.clone())? {
-                return ParseError::interrupt(interrupt);
+        } else if arg.starts_with("-") && ! options_ended {
+            match defs.read_option(arg, index, &mut args, &mut staged, settings, help.clone()) {
+                Ok(Some(interrupt)) => return ParseError::interrupt(interrupt),
+                Ok(None) => {}
+                Err(err) => failures.record(Err(err))?,
             }
         
         // Positional
-        } else if ! defs.positional.is_empty() {
-            let (_name, target) = defs.positional.pop_front().unwrap();
-            match target.parse(arg) {
-                Ok(()) => {},
-                Err(msg) => return ParseError::parse(msg, help),
-            } // MAYBE: chain err
+        } else if staged.positional.len() < defs.positional.len() {
+            staged.positional.push(RawValue::given(arg, index));
         
         // Subcommand
-        } else if ! defs.subcommands.is_empty() {
-            if let Some(handler) = defs.subcommands.get_mut(arg) {
-                let rest = args.collect::<Vec<_>>();
-                let subprogram = format!("{} {}", program, arg);
-                return handler(subprogram, &rest);
+        } else if defs.has_subcommands() {
+            let name = match defs.aliases.get(arg) {
+                Some(name) => name.to_string(),
+                None => arg.to_string(),
+            };
+            let name = name.as_str();
+            if ! (defs.subcommands.contains_key(name) || defs.commands.contains_key(name)) {
+                if name == "help" {
+                    let path: Vec<_> = args.collect();
+                    defs.write_subcommand_help(help, &path, &mut settings.output_sink.borrow_mut())?;
+                    return ParseError::interrupt(Cow::Borrowed("help"));
+                }
+                failures.record(defs.unknown_subcommand(arg, index, help))?;
+                // The rest of the arguments belong to the unknown subcommand.
+                return failures.finish().map(|_| (Matches::default(), Vec::new()));
+            }
+            // The global options are read by the subcommand from here on.
+            let globals = if defs.commands.contains_key(name) {
+                Some(defs.split_globals(&mut staged))
             } else {
-                return ParseError::parse(format!("Unknown subcommand: '{}'", arg), help);
+                None
+            };
+            // The options before the subcommand are only assigned if the
+            // subcommand succeeds as well.
+            defs.read_fallbacks(&mut staged, settings, &section, help.clone(), &mut failures)?;
+            defs.check_required(&staged, help.clone(), &mut failures)?;
+            if globals.is_none() {
+                defs.check_relations(&staged, &[], help.clone(), &mut failures)?;
+            }
+            defs.check_choices(&mut staged, help.clone(), &mut failures)?;
+            defs.prepare(&mut staged, originals, help.clone(), &mut failures)?;
+            failures.finish()?;
+            let rest = args.map(|(_, arg)| arg).collect::<Vec<_>>();
+            let subprogram = format!("{} {}", program, name);
+            
+            if let Some(globals) = globals {
+                let children = defs.commands.remove(name).unwrap();
+                let inherited = Inherited {
+                    globals,
+                    help: help.options.iter().filter(|o| o.global).cloned().collect(),
+                    offset: index + 1,
+                };
+                let (sub_matches, mut pending) = run_level(subprogram, &rest, originals, positions, children, Some(inherited), settings, checked)?;
+                // The relations are checked once the global options have been
+                // read by the subcommand as well.
+                {
+                    let below: Vec<_> = pending.iter().map(|&(_, ref staged)| staged).collect();
+                    defs.check_relations(&staged, &below, help.clone(), &mut failures)?;
+                }
+                failures.finish()?;
+                let matches = defs.matches(&staged, Some((name.to_string(), sub_matches)));
+                pending.push((defs, staged));
+                return Ok((matches, pending));
             }
+            
+            let (result, sub_matches) = capture(|| {
+                (defs.subcommands.get_mut(name).unwrap())(subprogram, &rest)
+            });
+            result?;
+            let matches = defs.matches(&staged, Some((name.to_string(), sub_matches.unwrap_or_default())));
+            return Ok((matches, vec![(defs, staged)]));
         
         // Trail
+        } else if defs.trail.is_some() {
+            staged.trail.push(RawValue::given(arg, index));
         } else {
-            if let Some((_, ref mut satisfied, ref mut target)) = defs.trail {
-                match target.parse_and_add(arg) {
-                    Ok(()) => {},
-                    Err(msg) => return ParseError::parse(msg, help),
-                }; // TODO: chain err
-                *satisfied = true;
-            } else {
-                return ParseError::parse(format!("Unexpected argument '{}'", arg), help);
-            }            
+            failures.record(ParseFailure::new(ErrorKind::UnexpectedArgument, 
+                format!("Unexpected argument '{}'", arg), help.clone())
+                .at(Some(index)).text(arg).fail())?;
         }
     }
     
-    if let Some((name, _)) = defs.positional.pop_front() {
-        return ParseError::parse(format!("Missing positional argument '{}'", name), help);
+    defs.read_fallbacks(&mut staged, settings, &section, help.clone(), &mut failures)?;
+    
+    for &(ref name, _) in defs.positional.iter().skip(staged.positional.len()) {
+        failures.record(ParseFailure::new(ErrorKind::MissingPositional, 
+            format!("Missing positional argument '{}'", name), help.clone())
+            .name(name).fail())?;
     }
     
-    if let Some((name, satisfied, _)) = defs.trail {
-        if ! satisfied {
-            return ParseError::parse(format!("Expected at least one trailing argument for '{}'", name), help);
+    defs.check_required(&staged, help.clone(), &mut failures)?;
+    
+    if let Some((ref name, optional, _)) = defs.trail {
+        if ! optional && staged.trail.is_empty() {
+            failures.record(ParseFailure::new(ErrorKind::MissingPositional, 
+                format!("Expected at least one trailing argument for '{}'", name), help.clone())
+                .name(name).fail())?;
         }
     }
     
-    if ! defs.subcommands.is_empty() {
-        return ParseError::parse(format!("No subcommand specified"), help);
+    if defs.has_subcommands() {
+        failures.record(ParseFailure::new(ErrorKind::MissingSubcommand, 
+            "No subcommand specified", help.clone()).fail())?;
     }
     
-    Ok(())
+    defs.check_relations(&staged, &[], help.clone(), &mut failures)?;
+    defs.check_choices(&mut staged, help.clone(), &mut failures)?;
+    
+    defs.prepare(&mut staged, originals, help, &mut failures)?;
+    failures.finish()?;
+    let matches = defs.matches(&staged, None);
+    Ok((matches, vec![(defs, staged)]))
 }
 
 /// Parses the given arguments and updates the defined variables with them.
@@ -308,15 +1883,485 @@ pub fn parse<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], definitions
     -> Result<(), ParseError<'def>>
   where T: Borrow<str> 
 { 
-    match parse_plain(program, args, definitions) {
+    parse_with(program, args, definitions, &ParseSettings::new())
+}
+
+/// Like `parse`, but with the given settings.
+pub fn parse_with<'def, 'tar, T, P: Into<String>>(program: P, args: &[T], 
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+  where T: Borrow<str> 
+{ 
+    report_failures(parse_plain_with(program, args, definitions, settings), settings)
+}
+
+/// Parses arguments as the OS gave them, like those of `env::args_os`, and
+/// handles errors like `parse`.
+///
+/// Option names must be valid UTF-8, but values are given untouched to the
+/// targets of the `os_*` constructors of `ArgDef`, like a `PathBuf`. Other
+/// targets, and `Matches`, see arguments that aren't valid UTF-8 converted
+/// lossily, unless they implement `prepare_raw`. On platforms other than 
+/// Unix, those values are converted lossily for every target.
+///
+/// # Example
+/// ```no_run
+/// # use playground::*;
+/// # use std::env;
+/// # use std::ffi::OsString;
+/// # use std::path::PathBuf;
+/// let mut path = PathBuf::new();
+/// let args: Vec<OsString> = env::args_os().skip(1).collect();
+/// parse_os("backup", &args, vec![ArgDef::os_pos("path", &mut path)]);
+/// ```
+pub fn parse_os<'def, 'tar, A, P: Into<String>>(program: P, args: &[A], 
+        definitions: Vec<ArgDef<'def, 'tar>>) 
+    -> Result<(), ParseError<'def>>
+  where A: AsRef<OsStr>
+{ 
+    parse_os_with(program, args, definitions, &ParseSettings::new())
+}
+
+/// Like `parse_os`, but with the given settings.
+pub fn parse_os_with<'def, 'tar, A, P: Into<String>>(program: P, args: &[A], 
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+  where A: AsRef<OsStr>
+{ 
+    report_failures(parse_plain_os_with(program, args, definitions, settings), settings)
+}
+
+/// Parses arguments as the OS gave them, like `parse_os`, but gives errors
+/// like `parse_plain`.
+pub fn parse_plain_os<'def, 'tar, A, P: Into<String>>(program: P, args: &[A], 
+        definitions: Vec<ArgDef<'def, 'tar>>) 
+    -> Result<(), ParseError<'def>>
+  where A: AsRef<OsStr>
+{ 
+    parse_plain_os_with(program, args, definitions, &ParseSettings::new())
+}
+
+/// Like `parse_plain_os`, but with the given settings.
+pub fn parse_plain_os_with<'def, 'tar, A, P: Into<String>>(program: P, args: &[A], 
+        definitions: Vec<ArgDef<'def, 'tar>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+  where A: AsRef<OsStr>
+{ 
+    let (args, originals) = os::split_originals(args);
+    run(program, &args, originals, definitions, settings, false).map(|_| ())
+}
+
+/// Handles the errors of a parse like `parse` does.
+pub fn report_failures<'def>(result: Result<(), ParseError<'def>>, settings: &ParseSettings) 
+    -> Result<(), ParseError<'def>>
+{
+    match result {
         Err(ParseError::InvalidDefinitions(msg)) => {
             panic!("Invalid definitions: {}", msg);
         }
-        Err(ParseError::ParseFailed(msg, help)) => {
-            println!("Parse failed: {}", msg);
-            help.print_usage();
+        Err(ParseError::ParseFailed(failure)) => {
+            let mut sink = settings.error_sink.borrow_mut();
+            report(&mut sink, &failure);
+            failure.help.write_usage_to(&mut sink);
+            Err(ParseError::SubParseFailed)
+        }
+        Err(ParseError::ParseFailures(failures)) => {
+            let mut sink = settings.error_sink.borrow_mut();
+            for failure in failures.iter() {
+                report(&mut sink, failure);
+            }
+            if let Some(failure) = failures.first() {
+                failure.help.write_usage_to(&mut sink);
+            }
             Err(ParseError::SubParseFailed)
         }
         other => other,
     }
 }
+
+/// Writes a failure to the given sink, as `Parse failed: <message>`.
+fn report<'def>(sink: &mut Sink, failure: &ParseFailure<'def>) {
+    let line = format!("{} {}\n", sink.style.error("Parse failed:"), failure);
+    sink.write_str(&line);
+}
+
+#[cfg(test)]
+mod tests {
+    use argdef::ArgDef;
+    use settings::ParseSettings;
+    use style::{Captured, Style};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use std::marker::PhantomData;
+    use std::str::FromStr;
+    use super::*;
+    
+    /// Returns the failure of a parse that was expected to fail.
+    fn failure_of<'def, T: fmt::Debug>(result: Result<T, ParseError<'def>>) -> ParseFailure<'def> {
+        match result {
+            Err(ParseError::ParseFailed(failure)) => failure,
+            other => panic!("Expected a parse failure, got {:?}", other),
+        }
+    }
+    
+    /// The definitions of a program whose `--json` and `--yaml` are
+    /// exclusive, and one of which is required along with `--out`.
+    fn formats() -> Vec<ArgDef<'static, 'static>> {
+        vec![
+            ArgDef::unbound_flag("json"),
+            ArgDef::unbound_flag("yaml"),
+            ArgDef::unbound_option("out").default("-"),
+            ArgDef::unbound_flag("quiet").conflicts_with("verbose"),
+            ArgDef::unbound_flag("verbose").requires("out"),
+            ArgDef::group("format", vec!["json", "yaml"]).exclusive().required(),
+        ]
+    }
+    
+    #[test]
+    fn exclusive_groups_allow_one_member() {
+        let matches = parse_matches("tool", &["--yaml"], formats()).unwrap();
+        assert_eq!(1, matches.occurrences("yaml"));
+        
+        let failure = failure_of(parse_matches("tool", &["--json", "--yaml"], formats()));
+        assert_eq!(ErrorKind::ArgumentConflict, failure.kind);
+        assert_eq!("'--json' and '--yaml' cannot be used together", failure.message);
+        assert_eq!(Some("format".to_string()), failure.name);
+    }
+    
+    #[test]
+    fn required_groups_need_a_member() {
+        let failure = failure_of(parse_matches("tool", &[] as &[&str], formats()));
+        assert_eq!(ErrorKind::MissingRequirement, failure.kind);
+        assert_eq!("One of '--json', '--yaml' is required", failure.message);
+        assert_eq!(Some("format".to_string()), failure.name);
+    }
+    
+    #[test]
+    fn requires_ignores_default_values() {
+        let matches = parse_matches("tool", &["--json", "--verbose", "--out", "a.txt"], formats()).unwrap();
+        assert_eq!(Some("a.txt"), matches.value_of("out"));
+        
+        let failure = failure_of(parse_matches("tool", &["--json", "--verbose"], formats()));
+        assert_eq!(ErrorKind::MissingRequirement, failure.kind);
+        assert_eq!("'--verbose' requires '--out'", failure.message);
+        assert_eq!(Some("verbose".to_string()), failure.name);
+    }
+    
+    #[test]
+    fn conflicts_with_rejects_both() {
+        let matches = parse_matches("tool", &["--json", "--quiet"], formats()).unwrap();
+        assert_eq!(1, matches.occurrences("quiet"));
+        
+        let failure = failure_of(parse_matches("tool", &["--json", "--quiet", "--verbose", "--out", "x"], formats()));
+        assert_eq!(ErrorKind::ArgumentConflict, failure.kind);
+        assert_eq!("'--quiet' cannot be used with '--verbose'", failure.message);
+        assert_eq!(Some("quiet".to_string()), failure.name);
+    }
+    
+    #[test]
+    fn groups_see_global_members_given_to_subcommands() {
+        let definitions = || vec![
+            ArgDef::unbound_flag("json").global(),
+            ArgDef::unbound_flag("yaml").global(),
+            ArgDef::group("format", vec!["json", "yaml"]).exclusive().required(),
+            ArgDef::subcommand("list", vec![]),
+        ];
+        let matches = parse_matches("tool", &["list", "--json"], definitions()).unwrap();
+        assert_eq!(1, matches.subcommand().unwrap().1.occurrences("json"));
+        
+        let failure = failure_of(parse_matches("tool", &["--json", "list", "--yaml"], definitions()));
+        assert_eq!("'--json' and '--yaml' cannot be used together", failure.message);
+    }
+    
+    /// The definitions of a program with a global `--cert`, which `--key`
+    /// requires.
+    fn certified<'tar>(cert: &'tar mut bool, key: &'tar mut bool, name: &'tar mut String) 
+            -> Vec<ArgDef<'static, 'tar>> {
+        vec![
+            ArgDef::flag("cert", cert).global(),
+            ArgDef::flag("key", key).requires("cert"),
+            ArgDef::subcommand("remote", vec![
+                ArgDef::pos("name", name),
+            ]),
+        ]
+    }
+    
+    #[test]
+    fn global_options_satisfy_relations() {
+        let (mut cert, mut key, mut name) = (false, false, String::new());
+        parse_plain("git", &["--cert", "--key", "remote", "origin"], 
+            certified(&mut cert, &mut key, &mut name)).unwrap();
+        assert_eq!((true, true, "origin"), (cert, key, name.as_str()));
+        
+        let (mut cert, mut key, mut name) = (false, false, String::new());
+        parse_plain("git", &["--key", "remote", "--cert", "origin"], 
+            certified(&mut cert, &mut key, &mut name)).unwrap();
+        assert_eq!((true, true, "origin"), (cert, key, name.as_str()));
+    }
+    
+    #[test]
+    fn missing_global_options_break_relations() {
+        let (mut cert, mut key, mut name) = (false, false, String::new());
+        let failure = failure_of(parse_plain("git", &["--key", "remote", "origin"], 
+            certified(&mut cert, &mut key, &mut name)));
+        assert_eq!(ErrorKind::MissingRequirement, failure.kind);
+        assert_eq!("'--key' requires '--cert'", failure.message);
+        assert_eq!((false, false, ""), (cert, key, name.as_str()));
+    }
+    
+    /// Returns settings that read environment variables from the given
+    /// pairs instead of the process environment.
+    fn environment(vars: &[(&'static str, &'static str)]) -> ParseSettings {
+        let vars: HashMap<_, _> = vars.iter().cloned().collect();
+        ParseSettings::new().env_lookup(move |name| vars.get(name).map(|value| value.to_string()))
+    }
+    
+    #[test]
+    fn bound_variables_are_read_when_not_given() {
+        let settings = environment(&[("PORT", "8080")]);
+        let mut port: Option<u16> = None;
+        parse_plain_with("server", &[] as &[&str], vec![
+            ArgDef::option("port", &mut port).env("PORT"),
+        ], &settings).unwrap();
+        assert_eq!(Some(8080), port);
+        
+        let mut port: Option<u16> = None;
+        parse_plain_with("server", &["--port", "80"], vec![
+            ArgDef::option("port", &mut port).env("PORT"),
+        ], &settings).unwrap();
+        assert_eq!(Some(80), port);
+    }
+    
+    #[test]
+    fn prefixes_map_names_to_variables() {
+        let settings = environment(&[("APP_LOG_LEVEL", "debug"), ("LOG_LEVEL", "error")])
+            .env_prefix("APP");
+        let matches = parse_matches_with("app", &[] as &[&str], vec![
+            ArgDef::unbound_option("log-level"),
+        ], &settings).unwrap();
+        assert_eq!(Some("debug"), matches.value_of("log-level"));
+        assert_eq!(Some(&ValueSource::Environment("APP_LOG_LEVEL".to_string())), 
+            matches.source("log-level"));
+    }
+    
+    #[test]
+    fn flags_read_words_from_variables() {
+        let settings = environment(&[("DRY_RUN", "yes")]);
+        let mut dry_run = false;
+        parse_plain_with("tool", &[] as &[&str], vec![
+            ArgDef::flag("dry-run", &mut dry_run).env("DRY_RUN"),
+        ], &settings).unwrap();
+        assert!(dry_run);
+    }
+    
+    #[test]
+    fn invalid_variables_name_their_source() {
+        let settings = environment(&[("PORT", "http"), ("DRY_RUN", "maybe")]);
+        let mut port: Option<u16> = None;
+        let failure = failure_of(parse_plain_with("server", &[] as &[&str], vec![
+            ArgDef::option("port", &mut port).env("PORT"),
+        ], &settings));
+        assert_eq!(ErrorKind::InvalidValue, failure.kind);
+        assert_eq!("Invalid value 'http' for '--port': invalid digit found in string \
+            (from environment variable 'PORT')", failure.message);
+        assert_eq!((None, Some("http".to_string())), (failure.index, failure.text));
+        assert_eq!(None, port);
+        
+        let mut dry_run = false;
+        let failure = failure_of(parse_plain_with("tool", &[] as &[&str], vec![
+            ArgDef::flag("dry-run", &mut dry_run).env("DRY_RUN"),
+        ], &settings));
+        assert_eq!(ErrorKind::InvalidValue, failure.kind);
+        assert_eq!("Invalid value 'maybe' for flag 'dry-run' (from environment variable 'DRY_RUN')", 
+            failure.message);
+    }
+    
+    /// Returns settings that capture the errors of a parse, and whatever
+    /// interrupts write.
+    fn captured(style: Style) -> (ParseSettings, Captured, Captured) {
+        let (errors, error_text) = Captured::sink(style);
+        let (output, output_text) = Captured::sink(style);
+        (ParseSettings::new().error_sink(errors).output_sink(output), error_text, output_text)
+    }
+    
+    #[test]
+    fn failures_are_reported_to_the_error_sink() {
+        let (settings, errors, output) = captured(Style::plain());
+        let mut verbose = false;
+        let result = parse_with("tool", &["--verbos"], vec![
+            ArgDef::flag("verbose", &mut verbose),
+        ], &settings);
+        match result {
+            Err(ParseError::SubParseFailed) => {}
+            other => panic!("Expected a reported failure, got {:?}", other),
+        }
+        assert_eq!("Parse failed: Unknown option '--verbos'. Did you mean '--verbose'?\n\
+            Usage: tool [ OPTIONS ]\n", errors.text());
+        assert_eq!("", output.text());
+    }
+    
+    #[test]
+    fn styled_failures_are_reported_in_color() {
+        let (settings, errors, _) = captured(Style::styled());
+        let _ = parse_with("tool", &["extra"], vec![], &settings);
+        assert_eq!("\x1b[1;31mParse failed:\x1b[0m Unexpected argument 'extra'\n\
+            \x1b[1mUsage:\x1b[0m tool\n", errors.text());
+    }
+    
+    #[test]
+    fn unknown_shells_fail_the_parse() {
+        let (settings, errors, output) = captured(Style::plain());
+        let result = parse_with("tool", &["--completions", "tcsh"], vec![::completions_arg()], &settings);
+        match result {
+            Err(ParseError::SubParseFailed) => {}
+            other => panic!("Expected a reported failure, got {:?}", other),
+        }
+        assert!(errors.text().starts_with("Parse failed: Invalid value 'tcsh' for '--completions'. \
+            Possible values: bash, zsh, fish\n"));
+        assert_eq!("", output.text());
+    }
+    
+    #[test]
+    fn collected_failures_follow_the_arguments() {
+        let settings = ParseSettings::new().collect_errors();
+        let (mut count, mut name): (Option<u8>, String) = (None, String::new());
+        let result = parse_plain_with("tool", &["--count", "x", "--bogus", "a", "b"], vec![
+            ArgDef::option("count", &mut count),
+            ArgDef::pos("name", &mut name),
+            ArgDef::unbound_option("size").required(),
+        ], &settings);
+        let failures = match result {
+            Err(ParseError::ParseFailures(failures)) => failures,
+            other => panic!("Expected collected failures, got {:?}", other),
+        };
+        let found: Vec<_> = failures.iter().map(|failure| (failure.kind, failure.index)).collect();
+        assert_eq!(vec![
+            (ErrorKind::InvalidValue, Some(1)),
+            (ErrorKind::UnknownOption, Some(2)),
+            (ErrorKind::UnexpectedArgument, Some(4)),
+            (ErrorKind::MissingOption, None),
+        ], found);
+    }
+    
+    #[test]
+    fn missing_positionals_come_before_the_passthrough() {
+        let (mut pattern, mut rest) = (String::new(), Vec::new());
+        parse_plain("tool", &["--", "-x", "a", "--b"], vec![
+            ArgDef::pos("pattern", &mut pattern),
+            ArgDef::passthrough("rest", &mut rest),
+        ]).unwrap();
+        assert_eq!("-x", pattern);
+        assert_eq!(vec!["a", "--b"], rest);
+    }
+    
+    #[test]
+    fn positionals_given_before_the_passthrough_are_kept() {
+        let (mut pattern, mut level) = (String::new(), String::new());
+        let (mut files, mut rest): (Vec<String>, _) = (Vec::new(), Vec::new());
+        parse_plain("tool", &["p", "--", "-x", "a"], vec![
+            ArgDef::pos("pattern", &mut pattern),
+            ArgDef::pos("level", &mut level).default("3"),
+            ArgDef::trail("files", true, &mut files),
+            ArgDef::passthrough("rest", &mut rest),
+        ]).unwrap();
+        assert_eq!(("p", "3"), (pattern.as_str(), level.as_str()));
+        assert!(files.is_empty());
+        assert_eq!(vec!["-x", "a"], rest);
+    }
+    
+    #[test]
+    fn attached_short_values_may_start_with_equals() {
+        let (mut verbose, mut output): (bool, Option<String>) = (false, None);
+        parse_plain("tool", &["-o=a.txt"], vec![
+            ArgDef::option("output", &mut output).short("o"),
+        ]).unwrap();
+        assert_eq!(Some("a.txt".to_string()), output);
+        
+        for &(arg, value) in &[("-vo=b.txt", "b.txt"), ("-vob.txt", "b.txt"), ("-vo==", "="), ("-vo=", "")] {
+            output = None;
+            parse_plain("tool", &[arg], vec![
+                ArgDef::flag("verbose", &mut verbose).short("v"),
+                ArgDef::option("output", &mut output).short("o"),
+            ]).unwrap();
+            assert_eq!(Some(value.to_string()), output, "{}", arg);
+        }
+        assert!(verbose);
+    }
+    
+    #[test]
+    fn handlers_give_the_matches_of_parse_matches() {
+        let matches = parse_matches("tool", &["sync", "--all"], vec![
+            ArgDef::cmd("sync", |program, args| {
+                // Like reading defaults from a file before the arguments.
+                let mut dry_run = false;
+                parse_plain(program.clone(), &["--dry-run"], vec![ArgDef::flag("dry-run", &mut dry_run)])?;
+                parse_matches(program, args, vec![ArgDef::unbound_flag("all"), ArgDef::unbound_flag("dry-run")])
+                    .map(|_| ())
+            }),
+        ]).unwrap();
+        let (name, sub_matches) = matches.subcommand().unwrap();
+        assert_eq!("sync", name);
+        assert!(sub_matches.is_present("all"));
+        assert!(! sub_matches.is_present("dry-run"));
+    }
+    
+    /// A target that borrows, with an error that is only `Display`.
+    #[derive(Debug, PartialEq)]
+    struct Borrowing<'a>(u8, PhantomData<&'a str>);
+    
+    struct Rejected;
+    
+    impl fmt::Display for Rejected {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Not a small number")
+        }
+    }
+    
+    impl<'a> FromStr for Borrowing<'a> {
+        type Err = Rejected;
+        fn from_str(value: &str) -> Result<Borrowing<'a>, Rejected> {
+            value.parse().map(|n| Borrowing(n, PhantomData)).map_err(|_| Rejected)
+        }
+    }
+    
+    #[test]
+    fn targets_need_not_be_static() {
+        fn read<'a>(args: &[&str]) -> Result<(Borrowing<'a>, Vec<Borrowing<'a>>), ParseError<'static>> {
+            let (mut level, mut rest) = (Borrowing(0, PhantomData), Vec::new());
+            parse_plain("tool", args, vec![
+                ArgDef::pos("level", &mut level),
+                ArgDef::trail("rest", true, &mut rest),
+            ])?;
+            Ok((level, rest))
+        }
+        let (level, rest) = read(&["3", "4"]).unwrap();
+        assert_eq!((3, vec![4]), (level.0, rest.iter().map(|n| n.0).collect::<Vec<_>>()));
+        let failure = failure_of(read(&["300"]));
+        assert_eq!(ErrorKind::InvalidValue, failure.kind);
+        assert!(failure.message.contains("Not a small number"), "{}", failure.message);
+    }
+    
+    #[test]
+    fn failures_in_response_files_point_at_the_file() {
+        let path = env::temp_dir().join("failures_in_response_files_point_at_the_file.txt");
+        File::create(&path).and_then(|mut file| file.write_all(b"a --bogus\nb")).unwrap();
+        let file = format!("@{}", path.display());
+        let settings = ParseSettings::new().response_files();
+        let (mut files, mut count): (Vec<String>, Option<u8>) = (Vec::new(), None);
+        let result = parse_plain_with("tool", &[file.as_str(), "--count", "x"], vec![
+            ArgDef::trail("files", false, &mut files),
+            ArgDef::option("count", &mut count),
+        ], &settings.collect_errors());
+        fs::remove_file(&path).unwrap();
+        let failures = match result {
+            Err(ParseError::ParseFailures(failures)) => failures,
+            other => panic!("Expected collected failures, got {:?}", other),
+        };
+        let found: Vec<_> = failures.iter().map(|failure| (failure.index, failure.text.clone())).collect();
+        assert_eq!(vec![
+            (Some(0), Some("--bogus".to_string())),
+            (Some(2), Some("x".to_string())),
+        ], found);
+    }
+}
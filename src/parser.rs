@@ -0,0 +1,157 @@
+use std::borrow::Borrow;
+use std::ffi::OsStr;
+use argdef::ArgDef;
+use help::Help;
+use os;
+use parse::{check_definitions, parse_plain_checked, report_failures, split_line, ParseError};
+use settings::ParseSettings;
+
+/// The function that defines the arguments of a `Parser`, binding them to the
+/// fields of the output.
+pub type DefineFn<'def, T> = Box<for<'a> Fn(&'a mut T) -> Vec<ArgDef<'def, 'a>> + 'def>;
+
+/// A parser that is set up once, and can then parse many argument lists.
+///
+/// Every parse starts from a fresh output value, which the definitions are
+/// bound to before the arguments are read. The definitions are checked once,
+/// when the parser is created, rather than on every parse.
+///
+/// # Example
+/// ```
+/// # use playground::*;
+/// #[derive(Default)]
+/// struct Command { verbose: bool, file: String }
+///
+/// let parser = Parser::new("shell", |cmd: &mut Command| vec![
+///     ArgDef::flag("verbose", &mut cmd.verbose),
+///     ArgDef::pos("file", &mut cmd.file),
+/// ]).unwrap();
+/// let first = parser.parse_plain(&["a.txt"]).unwrap();
+/// let second = parser.parse_plain(&["--verbose", "b.txt"]).unwrap();
+/// assert_eq!(("a.txt", true), (first.file.as_str(), second.verbose));
+/// ```
+pub struct Parser<'def, T> {
+    program: String,
+    define: DefineFn<'def, T>,
+    settings: ParseSettings,
+}
+
+impl<'def, T: Default> Parser<'def, T> {
+    /// Creates a parser for the given program, checking the definitions given
+    /// by `define` once, those of subcommands included.
+    pub fn new<P, F>(program: P, define: F) -> Result<Parser<'def, T>, ParseError<'def>>
+      where P: Into<String>,
+            F: for<'a> Fn(&'a mut T) -> Vec<ArgDef<'def, 'a>> + 'def
+    {
+        Parser::with_settings(program, define, ParseSettings::new())
+    }
+    
+    /// Like `new`, but every parse uses the given settings.
+    pub fn with_settings<P, F>(program: P, define: F, settings: ParseSettings)
+        -> Result<Parser<'def, T>, ParseError<'def>>
+      where P: Into<String>,
+            F: for<'a> Fn(&'a mut T) -> Vec<ArgDef<'def, 'a>> + 'def
+    {
+        {
+            let mut scratch = T::default();
+            check_definitions(define(&mut scratch), &settings)?;
+        }
+        Ok(Parser {
+            program: program.into(),
+            define: Box::new(define),
+            settings,
+        })
+    }
+    
+    /// Returns the help of this parser.
+    pub fn help(&self) -> Help<'def> {
+        let mut scratch = T::default();
+        let definitions = (self.define)(&mut scratch);
+        Help::with_settings(self.program.clone(), &definitions, &self.settings)
+    }
+    
+    /// Parses the given arguments into a fresh output, like `parse_plain`.
+    pub fn parse_plain<A: Borrow<str>>(&self, args: &[A]) -> Result<T, ParseError<'def>> {
+        let mut output = T::default();
+        self.parse_plain_into(args, &mut output)?;
+        Ok(output)
+    }
+    
+    /// Parses the given arguments into a fresh output, like `parse`.
+    pub fn parse<A: Borrow<str>>(&self, args: &[A]) -> Result<T, ParseError<'def>> {
+        let mut output = T::default();
+        report_failures(self.parse_plain_into(args, &mut output), &self.settings)?;
+        Ok(output)
+    }
+    
+    /// Parses arguments as the OS gave them into a fresh output, like
+    /// `parse_os`.
+    pub fn parse_os<A: AsRef<OsStr>>(&self, args: &[A]) -> Result<T, ParseError<'def>> {
+        let mut output = T::default();
+        let (args, originals) = os::split_originals(args);
+        let result = parse_plain_checked(self.program.clone(), &args, originals, 
+            (self.define)(&mut output), &self.settings);
+        report_failures(result, &self.settings)?;
+        Ok(output)
+    }
+    
+    /// Parses a command line given as text into a fresh output, like
+    /// `parse_str`.
+    pub fn parse_str(&self, line: &str) -> Result<T, ParseError<'def>> {
+        let mut output = T::default();
+        {
+            let definitions = (self.define)(&mut output);
+            let args = split_line(&self.program, line, &definitions, &self.settings)?;
+            parse_plain_checked(self.program.clone(), &args, Vec::new(), definitions, &self.settings)?;
+        }
+        Ok(output)
+    }
+    
+    /// Parses the given arguments into an existing output, like
+    /// `parse_plain`. The output is left untouched if the parse fails.
+    pub fn parse_plain_into<A: Borrow<str>>(&self, args: &[A], output: &mut T)
+            -> Result<(), ParseError<'def>> {
+        parse_plain_checked(self.program.clone(), args, Vec::new(), (self.define)(output), &self.settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[derive(Default)]
+    struct Output { verbose: usize, tags: Vec<String>, all: bool }
+    
+    fn define<'a>(output: &'a mut Output) -> Vec<ArgDef<'static, 'a>> {
+        vec![
+            ArgDef::count("verbose", &mut output.verbose),
+            ArgDef::repeated("tag", &mut output.tags),
+            ArgDef::subcommand("sync", vec![
+                ArgDef::flag("all", &mut output.all),
+            ]),
+        ]
+    }
+    
+    #[test]
+    fn every_parse_gives_a_fresh_output() {
+        let parser = Parser::new("tool", define).unwrap();
+        let first = parser.parse_plain(&["--verbose", "--tag", "a", "sync", "--all"]).unwrap();
+        let second = parser.parse_plain(&["--tag", "b", "sync"]).unwrap();
+        assert_eq!((1, vec!["a".to_string()], true), (first.verbose, first.tags, first.all));
+        assert_eq!((0, vec!["b".to_string()], false), (second.verbose, second.tags, second.all));
+    }
+    
+    #[test]
+    fn subcommand_definitions_are_checked_once() {
+        let result = Parser::new("tool", |output: &mut Output| vec![
+            ArgDef::subcommand("sync", vec![
+                ArgDef::flag("all", &mut output.all),
+                ArgDef::unbound_flag("all"),
+            ]),
+        ]);
+        match result {
+            Err(ParseError::InvalidDefinitions(msg)) => assert_eq!("Option 'all' defined twice.", msg),
+            _ => panic!("Expected the subcommand definitions to be rejected"),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::mem;
+
+/// The words of a text being split, and the word being read.
+struct Words {
+    words: Vec<(String, usize)>,
+    current: String,
+    // The line the current word starts on, if one is being read.
+    start: Option<usize>,
+}
+
+impl Words {
+    /// Starts a word on the given line, unless one is being read.
+    fn begin(&mut self, line: usize) {
+        if self.start.is_none() {
+            self.start = Some(line);
+        }
+    }
+    
+    /// Adds a character to the current word.
+    fn push(&mut self, c: char, line: usize) {
+        self.begin(line);
+        self.current.push(c);
+    }
+    
+    /// Ends the current word, if one is being read.
+    fn end(&mut self) {
+        if let Some(line) = self.start.take() {
+            let word = mem::replace(&mut self.current, String::new());
+            self.words.push((word, line));
+        }
+    }
+}
+
+/// Splits a text into words like a POSIX shell does, along with the line
+/// each word starts on, starting at 1. Nothing is expanded.
+///
+/// Words are separated by whitespace, including newlines. Single quotes keep
+/// everything up to the next single quote, and double quotes keep everything
+/// up to the next double quote, except that `\"`, `\\`, `\$` and `` \` ``
+/// are unescaped. Outside quotes a backslash escapes any character, and a
+/// `#` at the start of a word comments out the rest of the line. A backslash
+/// before a newline joins the lines.
+///
+/// Fails with the line and a reason on an unclosed quote or a trailing
+/// backslash.
+pub fn split(text: &str) -> Result<Vec<(String, usize)>, (usize, String)> {
+    let mut words = Words { words: Vec::new(), current: String::new(), start: None };
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                words.end();
+                line += 1;
+            }
+            c if c.is_whitespace() => words.end(),
+            '#' if words.start.is_none() => {
+                while let Some(c) = chars.next() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => line += 1,
+                Some(c) => words.push(c, line),
+                None => return Err((line, format!("Trailing backslash"))),
+            },
+            '\'' => {
+                let opened = line;
+                words.begin(line);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            words.push(c, line);
+                        }
+                        None => return Err((opened, format!("Unclosed single quote"))),
+                    }
+                }
+            }
+            '"' => {
+                let opened = line;
+                words.begin(line);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.peek().cloned() {
+                            Some('\n') => {
+                                chars.next();
+                                line += 1;
+                            }
+                            Some(c) if "\"\\$`".contains(c) => {
+                                chars.next();
+                                words.push(c, line);
+                            }
+                            _ => words.push('\\', line),
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            words.push(c, line);
+                        }
+                        None => return Err((opened, format!("Unclosed double quote"))),
+                    }
+                }
+            }
+            c => words.push(c, line),
+        }
+    }
+    words.end();
+    Ok(words.words)
+}
+
+/// A problem found while expanding response files.
+struct ExpandError {
+    reason: String,
+    // Where the problem is, innermost first, like `b.txt:3`.
+    locations: Vec<String>,
+}
+
+impl ExpandError {
+    fn new(reason: String) -> ExpandError {
+        ExpandError { reason, locations: Vec::new() }
+    }
+    
+    fn at(mut self, path: &str, line: usize) -> ExpandError {
+        self.locations.push(format!("{}:{}", path, line));
+        self
+    }
+    
+    fn message(&self) -> String {
+        if self.locations.is_empty() {
+            self.reason.clone()
+        } else {
+            format!("{} (at {})", self.reason, self.locations.join(", included from "))
+        }
+    }
+}
+
+/// The response files being read, outermost first, and whether `--` has
+/// been seen.
+struct Expansion {
+    // (canonical path, path as given)
+    files: Vec<(PathBuf, String)>,
+    ended: bool,
+}
+
+/// Replaces every argument like `@args.txt` with the words of that file,
+/// split like by `split`. Arguments read from a file are expanded as well,
+/// and nothing is expanded after `--`. Every word is returned along with the
+/// index of the argument it is or was read from, and whether it is that 
+/// argument itself.
+///
+/// Fails with the index of the offending argument and a message that
+/// names the file and line of the problem.
+pub fn expand(args: &[&str]) -> Result<Vec<(String, usize, bool)>, (usize, String)> {
+    let mut expansion = Expansion { files: Vec::new(), ended: false };
+    let mut expanded = Vec::new();
+    for (index, arg) in args.iter().enumerate() {
+        let mut words = Vec::new();
+        expand_arg(arg, &mut expansion, &mut words)
+            .map_err(|err| (index, err.message()))?;
+        if words.len() == 1 && words[0] == *arg {
+            expanded.push((words.pop().unwrap(), index, true));
+        } else {
+            expanded.extend(words.into_iter().map(|word| (word, index, false)));
+        }
+    }
+    Ok(expanded)
+}
+
+/// Expands a single argument into `expanded`.
+fn expand_arg(arg: &str, expansion: &mut Expansion, expanded: &mut Vec<String>)
+        -> Result<(), ExpandError> {
+    if expansion.ended || ! arg.starts_with("@") || arg.len() == 1 {
+        if arg == "--" {
+            expansion.ended = true;
+        }
+        expanded.push(arg.to_string());
+        return Ok(());
+    }
+    
+    let name = &arg[1..];
+    let path = Path::new(name);
+    let mut text = String::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_string(&mut text)) {
+        return Err(ExpandError::new(format!("Could not read response file '{}': {}", name, err)));
+    }
+    // The same file may be named by different paths, like `a.txt` and `./a.txt`.
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if let Some(i) = expansion.files.iter().position(|&(ref p, _)| *p == canonical) {
+        let mut cycle: Vec<_> = expansion.files[i..].iter().map(|&(_, ref n)| n.as_str()).collect();
+        cycle.push(name);
+        return Err(ExpandError::new(format!("Response file includes itself: {}", cycle.join(" -> "))));
+    }
+    
+    let words = split(&text)
+        .map_err(|(line, reason)| ExpandError::new(reason).at(name, line))?;
+    expansion.files.push((canonical, name.to_string()));
+    for (word, line) in words {
+        expand_arg(&word, expansion, expanded).map_err(|err| err.at(name, line))?;
+    }
+    expansion.files.pop();
+    Ok(())
+}
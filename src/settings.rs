@@ -0,0 +1,124 @@
+use std::env;
+use std::rc::Rc;
+use std::cell::RefCell;
+use config::ConfigFile;
+use style::Sink;
+
+/// Settings for a parse that aren't tied to a single argument definition.
+pub struct ParseSettings {
+    /// A prefix that binds every option, flag and count to an environment
+    /// variable. See `ArgDef::env_var`.
+    pub env_prefix: Option<String>,
+    /// Reads an environment variable. Defaults to the process environment.
+    pub env_lookup: Box<Fn(&str) -> Option<String>>,
+    /// A config file read for the arguments not given on the command line
+    /// or through the environment.
+    pub config: Option<Rc<ConfigFile>>,
+    /// Whether the parse keeps going after a problem, to report every 
+    /// problem at once.
+    pub collect_errors: bool,
+    /// Whether arguments like `@args.txt` are replaced by the arguments
+    /// read from that file.
+    pub response_files: bool,
+    /// Where `parse` reports failures. Defaults to stderr, styled if it is a
+    /// terminal and `NO_COLOR` isn't set.
+    pub error_sink: RefCell<Sink>,
+    /// Where interrupts like `--help` and `--version` write their output.
+    /// Defaults to stdout, styled if it is a terminal and `NO_COLOR` isn't
+    /// set.
+    pub output_sink: RefCell<Sink>,
+}
+
+impl ParseSettings {
+    /// Creates the default settings.
+    pub fn new() -> ParseSettings {
+        ParseSettings {
+            env_prefix: None,
+            env_lookup: Box::new(|name| env::var(name).ok()),
+            config: None,
+            collect_errors: false,
+            response_files: false,
+            error_sink: RefCell::new(Sink::stderr()),
+            output_sink: RefCell::new(Sink::stdout()),
+        }
+    }
+    
+    /// Binds every option, flag and count to an environment variable named
+    /// by the given prefix and the argument name, like `APP_LOG_LEVEL`.
+    pub fn env_prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.env_prefix = Some(prefix.into());
+        self
+    }
+    
+    /// Replaces the function used to read environment variables, eg. to 
+    /// avoid the process environment in tests.
+    pub fn env_lookup<F>(mut self, lookup: F) -> Self 
+      where F: Fn(&str) -> Option<String> + 'static
+    {
+        self.env_lookup = Box::new(lookup);
+        self
+    }
+    
+    /// Reads values from the given config file. The values of a parse are
+    /// taken from the section matching its command path, so that `epub create`
+    /// reads the `[create]` section if `create` is defined by 
+    /// `ArgDef::subcommand`. Global options fall back to the sections of the
+    /// enclosing commands.
+    ///
+    /// The handlers of `ArgDef::cmd` subcommands parse with settings of their
+    /// own, so they only read the file if they are given it as well.
+    ///
+    /// Values given on the command line override those from the environment,
+    /// which override those from the file, which override the defaults.
+    pub fn config<C: Into<Rc<ConfigFile>>>(mut self, config: C) -> Self {
+        self.config = Some(config.into());
+        self
+    }
+    
+    /// Keeps parsing after a problem is found, so that every unknown option,
+    /// invalid value and missing argument is reported together in a
+    /// `ParseError::ParseFailures`.
+    ///
+    /// Subcommands are only run when no problem was found before them, and
+    /// read their own settings.
+    pub fn collect_errors(mut self) -> Self {
+        self.collect_errors = true;
+        self
+    }
+    
+    /// Replaces every argument like `@args.txt` with the arguments read from
+    /// that file, for command lines longer than the system allows.
+    ///
+    /// The file is split into arguments like a shell would, with quotes,
+    /// backslash escapes and `#` comments, and can name other response
+    /// files in turn. Arguments after `--` are left as they are.
+    pub fn response_files(mut self) -> Self {
+        self.response_files = true;
+        self
+    }
+    
+    /// Reports failures to the given sink instead of stderr, eg. to capture
+    /// them in tests.
+    pub fn error_sink(mut self, sink: Sink) -> Self {
+        self.error_sink = RefCell::new(sink);
+        self
+    }
+    
+    /// Gives interrupts the given sink to write to instead of stdout, eg. to
+    /// capture the output of `--help` in tests.
+    pub fn output_sink(mut self, sink: Sink) -> Self {
+        self.output_sink = RefCell::new(sink);
+        self
+    }
+    
+    /// Reads the given environment variable.
+    pub fn var(&self, name: &str) -> Option<String> {
+        (self.env_lookup)(name)
+    }
+}
+
+impl Default for ParseSettings {
+    fn default() -> ParseSettings {
+        ParseSettings::new()
+    }
+}
@@ -0,0 +1,224 @@
+use std::env;
+use std::fmt;
+use std::io::{self, Write};
+
+/// A standard output stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Whether text is written with terminal styles, like bold section headers,
+/// colored option names and red errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub enabled: bool,
+}
+
+impl Style {
+    /// Writes text as it is.
+    pub fn plain() -> Style {
+        Style { enabled: false }
+    }
+    
+    /// Writes text with styles.
+    pub fn styled() -> Style {
+        Style { enabled: true }
+    }
+    
+    /// Styles the text written to the given stream, unless it isn't a
+    /// terminal or `NO_COLOR` is set.
+    pub fn detect(stream: Stream) -> Style {
+        let no_color = env::var_os("NO_COLOR").map(|v| ! v.is_empty()).unwrap_or(false);
+        Style { enabled: ! no_color && is_tty(stream) }
+    }
+    
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+    
+    /// Styles a section header, like `Usage:`.
+    pub fn header(&self, text: &str) -> String {
+        self.paint("1", text)
+    }
+    
+    /// Styles the name of an option, subcommand or positional argument.
+    pub fn name(&self, text: &str) -> String {
+        self.paint("36", text)
+    }
+    
+    /// Styles the start of an error message, like `Parse failed:`.
+    pub fn error(&self, text: &str) -> String {
+        self.paint("1;31", text)
+    }
+}
+
+#[cfg(unix)]
+fn is_tty(stream: Stream) -> bool {
+    extern "C" {
+        fn isatty(fd: i32) -> i32;
+    }
+    let fd = match stream {
+        Stream::Stdout => 1,
+        Stream::Stderr => 2,
+    };
+    unsafe { isatty(fd) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_tty(_stream: Stream) -> bool {
+    false
+}
+
+/// The `ioctl` request that reads the size of a terminal.
+#[cfg(any(target_os = "android", all(target_os = "linux",
+        any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64"))))]
+const TIOCGWINSZ: u64 = 0x5413;
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly",
+        target_os = "openbsd", target_os = "netbsd"))]
+const TIOCGWINSZ: u64 = 0x40087468;
+
+/// Returns the number of columns of the terminal that the stream writes to,
+/// or `None` if it doesn't write to a terminal.
+#[cfg(any(target_os = "android", all(target_os = "linux",
+        any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")),
+        target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly",
+        target_os = "openbsd", target_os = "netbsd"))]
+pub fn terminal_width(stream: Stream) -> Option<usize> {
+    use std::os::raw::{c_int, c_ulong};
+    #[repr(C)]
+    struct WinSize {
+        rows: u16,
+        columns: u16,
+        x_pixels: u16,
+        y_pixels: u16,
+    }
+    extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+    let fd = match stream {
+        Stream::Stdout => 1,
+        Stream::Stderr => 2,
+    };
+    let mut size = WinSize { rows: 0, columns: 0, x_pixels: 0, y_pixels: 0 };
+    let result = unsafe { ioctl(fd, TIOCGWINSZ as c_ulong, &mut size as *mut WinSize) };
+    if result == 0 && size.columns > 0 {
+        Some(size.columns as usize)
+    } else {
+        None
+    }
+}
+
+/// Returns the number of columns of the terminal that the stream writes to.
+/// The size of a terminal can't be read on this platform.
+#[cfg(not(any(target_os = "android", all(target_os = "linux",
+        any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm", target_arch = "aarch64")),
+        target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "dragonfly",
+        target_os = "openbsd", target_os = "netbsd")))]
+pub fn terminal_width(_stream: Stream) -> Option<usize> {
+    None
+}
+
+/// Where messages are written, along with how they are styled. Text can be
+/// written with `write_str`, or with `write!` through `Write`.
+///
+/// # Example
+/// Capturing the errors of a parse, with a writer that shares its buffer:
+/// ```
+/// # use playground::*;
+/// # use std::cell::RefCell;
+/// # use std::io::{self, Write};
+/// # use std::rc::Rc;
+/// #[derive(Clone)]
+/// struct Shared(Rc<RefCell<Vec<u8>>>);
+/// impl Write for Shared {
+///     fn write(&mut self, data: &[u8]) -> io::Result<usize> { self.0.borrow_mut().write(data) }
+///     fn flush(&mut self) -> io::Result<()> { Ok(()) }
+/// }
+///
+/// let buffer = Shared(Rc::new(RefCell::new(Vec::new())));
+/// let settings = ParseSettings::new().error_sink(Sink::new(buffer.clone(), Style::plain()));
+/// let _ = parse_with("tool", &["--nope"], vec![], &settings);
+/// assert!(buffer.0.borrow().starts_with(b"Parse failed: Unknown option '--nope'"));
+/// ```
+pub struct Sink {
+    writer: Box<Write>,
+    pub style: Style,
+}
+
+impl Sink {
+    /// Creates a sink writing to the given writer with the given style.
+    pub fn new<W: Write + 'static>(writer: W, style: Style) -> Sink {
+        Sink { writer: Box::new(writer), style }
+    }
+    
+    /// Writes to stdout, styled if it is a terminal.
+    pub fn stdout() -> Sink {
+        Sink::new(io::stdout(), Style::detect(Stream::Stdout))
+    }
+    
+    /// Writes to stderr, styled if it is a terminal.
+    pub fn stderr() -> Sink {
+        Sink::new(io::stderr(), Style::detect(Stream::Stderr))
+    }
+    
+    /// Writes the given text. Failures to write are ignored, like those of
+    /// `print!`.
+    pub fn write_str(&mut self, text: &str) {
+        let _ = self.writer.write_all(text.as_bytes());
+        let _ = self.writer.flush();
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sink").field("style", &self.style).finish()
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.writer.write(data)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// A writer that keeps what is written to it, to be read back in tests after
+/// it has been given to a sink.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct Captured(::std::rc::Rc<::std::cell::RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Captured {
+    /// Creates a sink writing to a new buffer in the given style, along with
+    /// the buffer.
+    pub fn sink(style: Style) -> (Sink, Captured) {
+        let captured = Captured::default();
+        (Sink::new(captured.clone(), style), captured)
+    }
+    
+    /// Returns everything written so far.
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Write for Captured {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(data)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}